@@ -1,34 +1,64 @@
 /// All you need to get started
-use crate::core::ecs::{systems::Builder, *};
+use crate::{
+    core::ecs::{systems::Builder, *},
+    plugin::{plugin_order, Plugin},
+    stage::{Stage, StageSchedules, Stages},
+    state::{State, StateBuilder, StateMachine, StateStack},
+    time::{FixedClock, FixedTime, Interpolation},
+};
+use anyhow::Result;
 use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::time::{Duration, Instant};
 
 pub const DEFAULT_THREAD_NUM: usize = 8;
 
 pub struct AppBuilder {
     pub world: World,
     pub resources: Resources,
-    pub builder: Builder,
+    /// Systems running every fixed tick, see [`FixedTime`](FixedTime).
+    pub fixed: Builder,
+    stages: Stages,
+    states: StateMachine,
+    plugins: Vec<Box<dyn Plugin>>,
     num_threads: usize,
+    frame_time: Option<Duration>,
 }
 
 impl AppBuilder {
-    /// Application with an empty `World`, and one `ResumeApp` in `Resources`
-    fn empty() -> Self {
+    /// Application with an empty `World`, and one `ResumeApp`,
+    /// `StateStack`, `FixedTime` and `Interpolation` in `Resources`,
+    /// opening a window if `windowed` and the `gui` feature is enabled.
+    #[cfg_attr(not(feature = "gui"), allow(unused_variables, unused_mut))]
+    fn empty(windowed: bool) -> Self {
         let mut world = World::default();
         let mut resources = Resources::default();
-        let mut builder = Schedule::builder();
+        let mut stages = Stages::default();
 
         resources.insert(ResumeApp::default());
         log::debug!("`ResumeApp` pushed to `Resources`");
 
+        resources.insert(StateStack::default());
+        resources.insert(FixedTime::default());
+        resources.insert(Interpolation::default());
+
         #[cfg(feature = "gui")]
-        super::gfx::canvas::event_routine(&mut world, &mut resources, &mut builder);
+        if windowed {
+            super::gfx::canvas::event_routine(
+                &mut world,
+                &mut resources,
+                stages.get_mut(Stage::PreUpdate),
+            );
+        }
 
         Self {
             world,
             resources,
-            builder,
+            fixed: Schedule::builder(),
+            stages,
+            states: StateMachine::default(),
+            plugins: Vec::new(),
             num_threads: DEFAULT_THREAD_NUM,
+            frame_time: None,
         }
     }
 
@@ -38,37 +68,192 @@ impl AppBuilder {
         self
     }
 
-    pub fn routine<T: Routine>(mut self) -> Self {
-        T::setup(&mut self.world, &mut self.resources, &mut self.builder);
+    /// Caps the no. of frames per second of [`App::run`](App::run),
+    /// sleeping the rest of every frame, uncapped by default.
+    pub fn max_frame_rate(mut self, fps: u32) -> Self {
+        self.frame_time = Some(Duration::from_secs_f64(1. / fps.max(1) as f64));
+        self
+    }
+
+    /// Sets up a routine in the `Update` stage.
+    pub fn routine<T: Routine>(self) -> Self {
+        self.routine_in::<T>(Stage::Update)
+    }
+
+    /// Sets up a routine in the `Update` stage.
+    pub fn routine_fn<F>(self, f: F) -> Self
+    where
+        F: FnMut(&mut World, &mut Resources, &mut Builder),
+    {
+        self.routine_fn_in(Stage::Update, f)
+    }
+
+    pub fn routine_in<T: Routine>(mut self, stage: Stage) -> Self {
+        self.with_stage(stage, T::setup);
         self
     }
 
-    pub fn routine_fn<F>(mut self, mut f: F) -> Self
+    pub fn routine_fn_in<F>(mut self, stage: Stage, f: F) -> Self
     where
         F: FnMut(&mut World, &mut Resources, &mut Builder),
     {
-        f(&mut self.world, &mut self.resources, &mut self.builder);
+        self.with_stage(stage, f);
+        self
+    }
+
+    /// Calls `f` with the builder of a stage, e.g. from a `Plugin`.
+    ///
+    /// ## Panics
+    /// * If `stage` was never added.
+    pub fn with_stage<F>(&mut self, stage: Stage, f: F)
+    where
+        F: FnOnce(&mut World, &mut Resources, &mut Builder),
+    {
+        f(
+            &mut self.world,
+            &mut self.resources,
+            self.stages.get_mut(stage),
+        );
+    }
+
+    /// Builder of the systems of a stage.
+    ///
+    /// ## Panics
+    /// * If `stage` was never added.
+    pub fn stage_mut(&mut self, stage: Stage) -> &mut Builder {
+        self.stages.get_mut(stage)
+    }
+
+    /// Replaces the former `builder` field, whose systems ran
+    /// before the fixed ticks like the `PreUpdate` stage.
+    #[deprecated(note = "use `stage_mut(Stage::PreUpdate)` or `stage_mut(Stage::Update)`")]
+    pub fn builder(&mut self) -> &mut Builder {
+        self.stage_mut(Stage::PreUpdate)
+    }
+
+    /// Replaces the former `render` field.
+    #[deprecated(note = "use `stage_mut(Stage::Render)`")]
+    pub fn render(&mut self) -> &mut Builder {
+        self.stage_mut(Stage::Render)
+    }
+
+    /// Adds a stage running right before `before`.
+    ///
+    /// ## Panics
+    /// * If `stage` already exists, or `before` isn't a frame stage.
+    pub fn add_stage_before(mut self, stage: Stage, before: Stage) -> Self {
+        self.stages.insert(stage, before, 0);
+        self
+    }
+
+    /// Adds a stage running right after `after`.
+    ///
+    /// ## Panics
+    /// * If `stage` already exists, or `after` isn't a frame stage.
+    pub fn add_stage_after(mut self, stage: Stage, after: Stage) -> Self {
+        self.stages.insert(stage, after, 1);
+        self
+    }
+
+    /// Sets the no. of fixed ticks per second.
+    pub fn tick_rate(mut self, tick_rate: u32) -> Self {
+        let max_ticks = get_expect::<FixedTime>(&self.resources).max_ticks;
+
+        let mut fixed = FixedTime::new(tick_rate);
+        fixed.max_ticks = max_ticks;
+        self.resources.insert(fixed);
+        self
+    }
+
+    /// Adds systems to the schedules of a state,
+    /// can be called several times for the same state.
+    pub fn state<S: State, F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut World, &mut Resources, &mut StateBuilder),
+    {
+        f(
+            &mut self.world,
+            &mut self.resources,
+            self.states.builder::<S>(),
+        );
+        self
+    }
+
+    /// Pushes a state when the app starts.
+    pub fn initial_state<S: State>(mut self) -> Self {
+        self.resources
+            .get_mut::<StateStack>()
+            .expect("`StateStack` does not exist in `Resources`")
+            .push::<S>();
+        self
+    }
+
+    /// Registers a plugin, set up when the app is built,
+    /// ignored if a plugin of the same name already is.
+    pub fn plugin<P: Plugin>(mut self, plugin: P) -> Self {
+        if self.plugins.iter().any(|p| p.name() == plugin.name()) {
+            log::debug!("plugin `{}` already registered", plugin.name());
+        } else {
+            self.plugins.push(Box::new(plugin));
+        }
+
         self
     }
 
+    /// Checks that the dependencies of every plugin are registered,
+    /// and that none of them conflict nor depend on each other in a cycle.
+    pub fn check_plugins(&self) -> Result<()> {
+        plugin_order(&self.plugins).map(|_| ())
+    }
+
+    /// Sets up the plugins after their dependencies, then builds the app.
+    pub fn try_build(mut self) -> Result<App> {
+        let order = plugin_order(&self.plugins)?;
+
+        let mut plugins: Vec<_> = std::mem::take(&mut self.plugins)
+            .into_iter()
+            .map(Some)
+            .collect();
+
+        for i in order {
+            let mut plugin = plugins[i].take().unwrap();
+            log::debug!("building plugin `{}`", plugin.name());
+            plugin.build(&mut self);
+        }
+
+        Ok(self.assemble())
+    }
+
+    /// ## Panics
+    /// * If the plugins are invalid, see [`check_plugins`](AppBuilder::check_plugins).
     pub fn build(self) -> App {
-        self.into()
+        self.try_build().expect("failed to build the app")
     }
-}
 
-impl From<AppBuilder> for App {
-    fn from(
-        AppBuilder {
+    fn assemble(self) -> App {
+        let AppBuilder {
             world,
             resources,
-            builder,
+            fixed,
+            stages,
+            mut states,
             num_threads,
-        }: AppBuilder,
-    ) -> Self {
-        Self {
+            frame_time,
+            ..
+        } = self;
+
+        states.build();
+
+        App {
             w: world,
             r: resources,
-            s: builder.into(),
+            fixed: fixed.into(),
+            stages: stages.build(),
+            states,
+            clock: FixedClock::default(),
+            frame_time,
+            started: false,
+            stopped: false,
             pool: ThreadPoolBuilder::new()
                 .num_threads(num_threads)
                 .build()
@@ -77,28 +262,151 @@ impl From<AppBuilder> for App {
     }
 }
 
+impl From<AppBuilder> for App {
+    fn from(builder: AppBuilder) -> Self {
+        builder.build()
+    }
+}
+
 pub struct App {
     w: World,
     r: Resources,
-    s: Schedule,
+    fixed: Schedule,
+    stages: StageSchedules,
+    states: StateMachine,
+    clock: FixedClock,
+    frame_time: Option<Duration>,
+    started: bool,
+    stopped: bool,
     pool: ThreadPool,
 }
 
 impl App {
     pub fn builder() -> AppBuilder {
-        AppBuilder::empty()
+        AppBuilder::empty(true)
+    }
+
+    /// Builder of an application never opening a window,
+    /// e.g. for a dedicated server or integration tests.
+    pub fn headless() -> AppBuilder {
+        AppBuilder::empty(false)
+    }
+
+    pub fn world(&self) -> &World {
+        &self.w
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.w
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.r
+    }
+
+    pub fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.r
     }
 
+    /// Runs frames in real time until `ResumeApp` is ended,
+    /// then the `Shutdown` stage.
     pub fn run(&mut self) {
         log::info!("start");
 
+        self.start();
+        self.clock = FixedClock::default();
+
         while **get_expect::<ResumeApp>(&self.r) {
-            self.s
-                .execute_in_thread_pool(&mut self.w, &mut self.r, &self.pool);
+            let start = Instant::now();
+            self.frame(None);
+
+            if let Some(frame_time) = self.frame_time {
+                if let Some(rest) = frame_time.checked_sub(start.elapsed()) {
+                    std::thread::sleep(rest);
+                }
+            }
         }
 
+        self.shutdown();
         log::info!("stop");
     }
+
+    /// Runs the `Shutdown` stage once,
+    /// to be called when done with [`tick`](App::tick) or [`run_for`](App::run_for).
+    pub fn shutdown(&mut self) {
+        if self.started && !self.stopped {
+            self.stopped = true;
+            self.stages.shutdown(&mut self.w, &mut self.r, &self.pool);
+        }
+    }
+
+    /// Runs a single frame with exactly one fixed tick,
+    /// whatever the time actually elapsed.
+    pub fn tick(&mut self) {
+        self.start();
+
+        let step = get_expect::<FixedTime>(&self.r).step();
+        self.frame(Some(step));
+    }
+
+    /// Runs `ticks` frames as [`tick`](App::tick) does, as fast as possible,
+    /// stopping early if `ResumeApp` is ended.
+    pub fn run_for(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            if !**get_expect::<ResumeApp>(&self.r) {
+                break;
+            }
+
+            self.tick();
+        }
+    }
+
+    /// Runs the `Startup` stage and enters the initial state, once.
+    fn start(&mut self) {
+        if !self.started {
+            self.started = true;
+            self.stages.startup(&mut self.w, &mut self.r, &self.pool);
+            self.states
+                .apply_transitions(&mut self.w, &mut self.r, &self.pool);
+        }
+    }
+
+    /// Runs the stages before `Update`, the fixed ticks due, `Update`,
+    /// the current state, then the remaining stages.
+    ///
+    /// The clock advances by `elapsed` if any, in real time otherwise.
+    fn frame(&mut self, elapsed: Option<Duration>) {
+        let (w, r, pool) = (&mut self.w, &mut self.r, &self.pool);
+        let update = self.stages.update_index();
+
+        self.stages.run(0..update, w, r, pool);
+
+        let ticks = {
+            let fixed = get_expect::<FixedTime>(r);
+            match elapsed {
+                Some(elapsed) => self.clock.advance_by(elapsed, &fixed),
+                None => self.clock.advance(&fixed),
+            }
+        };
+        for _ in 0..ticks {
+            r.get_mut::<FixedTime>().unwrap().next_tick();
+
+            self.fixed.execute_in_thread_pool(w, r, pool);
+            self.states.fixed_update(w, r, pool);
+        }
+
+        let alpha = self.clock.alpha(&get_expect::<FixedTime>(r));
+        r.get_mut::<Interpolation>()
+            .expect("`Interpolation` does not exist in `Resources`")
+            .0 = alpha;
+
+        self.stages.run(update..update + 1, w, r, pool);
+        self.states.update(w, r, pool);
+
+        let len = self.stages.frame_len();
+        self.stages.run(update + 1..len, w, r, pool);
+        self.states.apply_transitions(w, r, pool);
+    }
 }
 
 pub trait Routine {
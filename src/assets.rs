@@ -0,0 +1,936 @@
+//! Assets loaded from files in the background, such as textures,
+//! shaders, block definitions or models, shared through typed handles.
+use crate::{
+    core::{
+        ecs::{
+            systems::{Builder, Runnable},
+            *,
+        },
+        events::{new_channel, EventChannel},
+    },
+    jobs::{JobPool, Jobs, DEFAULT_MAX_IN_FLIGHT},
+};
+use anyhow::{bail, ensure, Result};
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+type AnyAsset = Box<dyn Any + Send + Sync>;
+
+/// Identifies an asset within `Assets`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AssetId(u64);
+
+/// A reference counted handle to an asset of type `T`,
+/// the asset is freed once every handle to it is dropped.
+pub struct Handle<T> {
+    id: AssetId,
+    rc: Arc<()>,
+    _m: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> AssetId {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            rc: self.rc.clone(),
+            _m: PhantomData::default(),
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle<{}>({})", type_name::<T>(), self.id.0)
+    }
+}
+
+/// Loads assets of a type from the bytes of a file.
+pub trait AssetLoader: Send + Sync + 'static {
+    type Asset: Send + Sync + 'static;
+
+    /// Extensions of the files this loader reads, without the dot.
+    fn extensions(&self) -> &[&'static str];
+
+    fn load(&self, bytes: &[u8], path: &Path) -> Result<Self::Asset>;
+}
+
+trait ErasedLoader: Send + Sync {
+    fn load_any(&self, bytes: &[u8], path: &Path) -> Result<AnyAsset>;
+}
+
+impl<L: AssetLoader> ErasedLoader for L {
+    fn load_any(&self, bytes: &[u8], path: &Path) -> Result<AnyAsset> {
+        Ok(Box::new(self.load(bytes, path)?))
+    }
+}
+
+/// Progress of the loading of an asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    /// See [`Assets::error`](Assets::error).
+    Failed,
+    /// The handle belongs to other `Assets`.
+    Unknown,
+}
+
+/// An event written whenever an asset changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetEvent {
+    Loaded(AssetId),
+    /// Loaded again after its file changed, see [`Assets::watch`](Assets::watch).
+    Reloaded(AssetId),
+    /// The asset keeps its previous value if it was reloading.
+    Failed(AssetId),
+    /// Freed after its last handle was dropped.
+    Removed(AssetId),
+}
+
+impl AssetEvent {
+    pub fn id(&self) -> AssetId {
+        match *self {
+            Self::Loaded(id) | Self::Reloaded(id) | Self::Failed(id) | Self::Removed(id) => id,
+        }
+    }
+}
+
+struct Entry {
+    path: Option<PathBuf>,
+    type_id: TypeId,
+    rc: Arc<()>,
+    state: LoadState,
+    error: Option<String>,
+    value: Option<AnyAsset>,
+}
+
+#[cfg(feature = "hot-reload")]
+type Watcher = std::sync::Mutex<(
+    notify::RecommendedWatcher,
+    std::sync::mpsc::Receiver<notify::DebouncedEvent>,
+)>;
+
+/// A resource holding every asset, loaded on the `JobPool`
+/// by the loader registered for their type and extension.
+///
+/// Paths are relative to the root directory of the assets.
+pub struct Assets {
+    root: PathBuf,
+    loaders: HashMap<(String, TypeId), Arc<dyn ErasedLoader>>,
+    entries: HashMap<AssetId, Entry>,
+    paths: HashMap<(PathBuf, TypeId), AssetId>,
+    next_id: u64,
+    jobs: Jobs<AssetId, Result<AnyAsset>>,
+    #[cfg(feature = "hot-reload")]
+    watcher: Option<Watcher>,
+}
+
+impl Assets {
+    pub fn new<P: Into<PathBuf>>(root: P, pool: &JobPool) -> Self {
+        Self {
+            root: root.into(),
+            loaders: HashMap::new(),
+            entries: HashMap::new(),
+            paths: HashMap::new(),
+            next_id: 0,
+            jobs: Jobs::new(pool, DEFAULT_MAX_IN_FLIGHT),
+            #[cfg(feature = "hot-reload")]
+            watcher: None,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Registers a loader for each of its extensions,
+    /// replacing the previous loader of the same asset type.
+    pub fn add_loader<L: AssetLoader>(&mut self, loader: L) {
+        let type_id = TypeId::of::<L::Asset>();
+        let extensions: Vec<String> = loader
+            .extensions()
+            .iter()
+            .map(|e| e.to_lowercase())
+            .collect();
+
+        let loader: Arc<dyn ErasedLoader> = Arc::new(loader);
+        for ext in extensions {
+            if self
+                .loaders
+                .insert((ext.clone(), type_id), loader.clone())
+                .is_some()
+            {
+                log::warn!(
+                    "replaced the loader of `{}` for `.{}` files",
+                    type_name::<L::Asset>(),
+                    ext
+                );
+            }
+        }
+    }
+
+    /// Starts loading the asset of a file in the background,
+    /// or returns the handle of the asset already loaded from it.
+    pub fn load<T, P>(&mut self, path: P) -> Result<Handle<T>>
+    where
+        T: Send + Sync + 'static,
+        P: AsRef<Path>,
+    {
+        let path = normalize(path.as_ref());
+        let type_id = TypeId::of::<T>();
+
+        if let Some(&id) = self.paths.get(&(path.clone(), type_id)) {
+            return Ok(self.handle(id));
+        }
+
+        self.loader_of(&path, type_id)?;
+
+        let id = self.push(Entry {
+            path: Some(path.clone()),
+            type_id,
+            rc: Arc::new(()),
+            state: LoadState::Loading,
+            error: None,
+            value: None,
+        });
+        self.paths.insert((path, type_id), id);
+        self.spawn(id)?;
+
+        Ok(self.handle(id))
+    }
+
+    /// Adds an asset that isn't loaded from a file, e.g. a generated texture.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, asset: T) -> Handle<T> {
+        let id = self.push(Entry {
+            path: None,
+            type_id: TypeId::of::<T>(),
+            rc: Arc::new(()),
+            state: LoadState::Loaded,
+            error: None,
+            value: Some(Box::new(asset)),
+        });
+
+        self.handle(id)
+    }
+
+    /// Loads an asset from its file again, keeping its value until done.
+    pub fn reload<T>(&mut self, handle: &Handle<T>) -> Result<()> {
+        self.spawn(handle.id)
+    }
+
+    /// The asset, if it's loaded.
+    pub fn get<T: 'static>(&self, handle: &Handle<T>) -> Option<&T> {
+        self.entries
+            .get(&handle.id)?
+            .value
+            .as_ref()?
+            .downcast_ref::<T>()
+    }
+
+    pub fn load_state<T>(&self, handle: &Handle<T>) -> LoadState {
+        self.entries
+            .get(&handle.id)
+            .map_or(LoadState::Unknown, |e| e.state)
+    }
+
+    pub fn is_loaded<T>(&self, handle: &Handle<T>) -> bool {
+        self.load_state(handle) == LoadState::Loaded
+    }
+
+    /// Why the last load of an asset failed.
+    pub fn error<T>(&self, handle: &Handle<T>) -> Option<&str> {
+        self.entries.get(&handle.id)?.error.as_deref()
+    }
+
+    /// Path of an asset, relative to the root directory.
+    pub fn path<T>(&self, handle: &Handle<T>) -> Option<&Path> {
+        self.entries.get(&handle.id)?.path.as_deref()
+    }
+
+    /// No. of assets, loaded or not.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Stores the assets loaded since the last call, reloads the ones whose
+    /// file changed, frees the ones without handles, and returns what happened.
+    pub fn poll(&mut self) -> Vec<AssetEvent> {
+        let mut events = Vec::new();
+
+        for id in self.changed_files() {
+            if let Err(e) = self.spawn(id) {
+                log::warn!("failed to reload asset: {}", e);
+            }
+        }
+
+        for (id, result) in self.jobs.poll() {
+            let entry = match self.entries.get_mut(&id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            match result.unwrap_or_else(|panic| Err(panic.into())) {
+                Ok(value) => {
+                    let reloaded = entry.value.is_some();
+                    entry.value = Some(value);
+                    entry.state = LoadState::Loaded;
+                    entry.error = None;
+
+                    events.push(if reloaded {
+                        AssetEvent::Reloaded(id)
+                    } else {
+                        AssetEvent::Loaded(id)
+                    });
+                }
+
+                Err(e) => {
+                    log::warn!("failed to load {:?}: {:#}", entry.path, e);
+
+                    if entry.value.is_none() {
+                        entry.state = LoadState::Failed;
+                    }
+                    entry.error = Some(format!("{:#}", e));
+                    events.push(AssetEvent::Failed(id));
+                }
+            }
+        }
+
+        let unused: Vec<AssetId> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| Arc::strong_count(&e.rc) == 1)
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in unused {
+            let entry = self.entries.remove(&id).unwrap();
+            if let Some(path) = entry.path {
+                self.paths.remove(&(path, entry.type_id));
+            }
+
+            self.jobs.cancel(&id);
+            events.push(AssetEvent::Removed(id));
+        }
+
+        events
+    }
+
+    /// Watches the root directory, reloading assets whose file changed.
+    #[cfg(feature = "hot-reload")]
+    #[doc(cfg(feature = "hot-reload"))]
+    pub fn watch(&mut self) -> Result<()> {
+        use notify::{RecursiveMode, Watcher as _};
+
+        self.root = self.root.canonicalize()?;
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::watcher(tx, std::time::Duration::from_millis(200))?;
+        watcher.watch(&self.root, RecursiveMode::Recursive)?;
+
+        self.watcher = Some(std::sync::Mutex::new((watcher, rx)));
+        Ok(())
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn changed_files(&self) -> Vec<AssetId> {
+        use notify::DebouncedEvent::*;
+
+        let watcher = match &self.watcher {
+            Some(watcher) => watcher.lock().unwrap(),
+            None => return Vec::new(),
+        };
+
+        let mut changed = Vec::new();
+        for event in watcher.1.try_iter() {
+            let path = match event {
+                Create(path) | Write(path) | Rename(_, path) => path,
+                Error(e, path) => {
+                    log::warn!("failed to watch {:?}: {}", path, e);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let path = match path.strip_prefix(&self.root) {
+                Ok(path) => normalize(path),
+                Err(_) => continue,
+            };
+
+            changed.extend(
+                self.entries
+                    .iter()
+                    .filter(|(_, e)| e.path.as_ref() == Some(&path))
+                    .map(|(&id, _)| id),
+            );
+        }
+
+        changed
+    }
+
+    #[cfg(not(feature = "hot-reload"))]
+    fn changed_files(&self) -> Vec<AssetId> {
+        Vec::new()
+    }
+
+    fn push(&mut self, entry: Entry) -> AssetId {
+        let id = AssetId(self.next_id);
+        self.next_id += 1;
+        self.entries.insert(id, entry);
+        id
+    }
+
+    fn handle<T>(&self, id: AssetId) -> Handle<T> {
+        Handle {
+            id,
+            rc: self.entries[&id].rc.clone(),
+            _m: PhantomData::default(),
+        }
+    }
+
+    fn loader_of(&self, path: &Path, type_id: TypeId) -> Result<Arc<dyn ErasedLoader>> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+
+        match self.loaders.get(&(ext, type_id)) {
+            Some(loader) => Ok(loader.clone()),
+            None => bail!("no loader registered for {:?}", path),
+        }
+    }
+
+    fn spawn(&mut self, id: AssetId) -> Result<()> {
+        let entry = &self.entries[&id];
+        let path = match &entry.path {
+            Some(path) => path.clone(),
+            None => bail!("asset {:?} wasn't loaded from a file", id),
+        };
+
+        let loader = self.loader_of(&path, entry.type_id)?;
+        let full = self.root.join(&path);
+
+        self.jobs.spawn(id, 0, move |_| {
+            let bytes = std::fs::read(&full)?;
+            loader.load_any(&bytes, &path)
+        });
+
+        Ok(())
+    }
+}
+
+/// Drops the `.` components of a path and resolves its `..` ones,
+/// so that e.g. `./a.png` and `b/../a.png` name the same asset as `a.png`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+
+    normalized
+}
+
+/// Returns a `System` polling `Assets` every frame,
+/// writing what happened to an `EventChannel<AssetEvent>`.
+pub fn assets_system() -> impl Runnable {
+    SystemBuilder::new("AssetsSystem")
+        .write_resource::<Assets>()
+        .write_resource::<EventChannel<AssetEvent>>()
+        .build(|_, _, (assets, channel), _| {
+            channel.iter_write(assets.poll());
+        })
+}
+
+/// Inserts a `JobPool` if there's none, `Assets` loaded from `root`
+/// with the built-in loaders, and their event channel,
+/// then adds the [`assets_system`](assets_system).
+///
+/// Meant to be used with `routine_fn`, e.g. `routine_fn(assets_routine("assets"))`.
+pub fn assets_routine<P>(root: P) -> impl FnMut(&mut World, &mut Resources, &mut Builder)
+where
+    P: Into<PathBuf> + Clone,
+{
+    move |_, r, b| {
+        insert_if_none(r, JobPool::default());
+
+        if !r.contains::<Assets>() {
+            let mut assets = Assets::new(root.clone(), &get_expect::<JobPool>(r));
+            assets.add_loader(SpirvLoader);
+            assets.add_loader(ObjLoader);
+            #[cfg(feature = "gui")]
+            assets.add_loader(ImageLoader);
+            #[cfg(feature = "serialize")]
+            assets.add_loader(BlockTypesLoader);
+
+            r.insert(assets);
+        }
+
+        if !r.contains::<EventChannel<AssetEvent>>() {
+            new_channel::<AssetEvent>(r);
+        }
+
+        b.add_system(assets_system());
+    }
+}
+
+/// A compiled shader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpirV(pub Vec<u32>);
+
+/// Loads `.spv` files, as compiled by the build script.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpirvLoader;
+
+impl AssetLoader for SpirvLoader {
+    type Asset = SpirV;
+
+    fn extensions(&self) -> &[&'static str] {
+        &["spv"]
+    }
+
+    fn load(&self, bytes: &[u8], _: &Path) -> Result<SpirV> {
+        ensure!(bytes.len() % 4 == 0, "SPIR-V isn't made of 32 bit words");
+
+        Ok(SpirV(
+            bytes
+                .chunks_exact(4)
+                .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+                .collect(),
+        ))
+    }
+}
+
+/// Loads images, to be uploaded with
+/// [`Texture::from_image`](crate::gfx::Texture::from_image).
+#[cfg(feature = "gui")]
+#[doc(cfg(feature = "gui"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImageLoader;
+
+#[cfg(feature = "gui")]
+impl AssetLoader for ImageLoader {
+    type Asset = image::DynamicImage;
+
+    fn extensions(&self) -> &[&'static str] {
+        &["png", "jpg", "jpeg", "bmp", "tga"]
+    }
+
+    fn load(&self, bytes: &[u8], _: &Path) -> Result<image::DynamicImage> {
+        Ok(image::load_from_memory(bytes)?)
+    }
+}
+
+/// Loads a list of block definitions from a `.ron` file,
+/// to be registered in a `BlockRegistry`.
+#[cfg(feature = "serialize")]
+#[doc(cfg(feature = "serialize"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockTypesLoader;
+
+#[cfg(feature = "serialize")]
+impl AssetLoader for BlockTypesLoader {
+    type Asset = Vec<crate::block::BlockType>;
+
+    fn extensions(&self) -> &[&'static str] {
+        &["ron"]
+    }
+
+    fn load(&self, bytes: &[u8], _: &Path) -> Result<Self::Asset> {
+        Ok(ron::de::from_bytes(bytes)?)
+    }
+}
+
+/// A vertex of a [`MeshData`](MeshData), as written in its file.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+}
+
+/// A mesh on the CPU, to be uploaded to vertex and index buffers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<MeshVertex>,
+    /// Triangles, as indices into `vertices`.
+    pub indices: Vec<u32>,
+}
+
+/// Loads the meshes of Wavefront `.obj` files, one per object or group,
+/// polygons are split into triangles and materials are ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ObjLoader;
+
+impl AssetLoader for ObjLoader {
+    type Asset = Vec<MeshData>;
+
+    fn extensions(&self) -> &[&'static str] {
+        &["obj"]
+    }
+
+    fn load(&self, bytes: &[u8], _: &Path) -> Result<Self::Asset> {
+        parse_obj(std::str::from_utf8(bytes)?)
+    }
+}
+
+fn parse_obj(src: &str) -> Result<Vec<MeshData>> {
+    let mut positions = Vec::new();
+    let mut tex_coords = Vec::new();
+    let mut normals = Vec::new();
+
+    let mut meshes = vec![MeshData::default()];
+    // Vertices of the current mesh, by their position, tex coords and normal indices
+    let mut vertices = HashMap::new();
+
+    for (n, line) in src.lines().enumerate() {
+        let n = n + 1;
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("v") => positions.push(obj_floats::<3>(words, n)?),
+            Some("vt") => tex_coords.push(obj_floats::<2>(words, n)?),
+            Some("vn") => normals.push(obj_floats::<3>(words, n)?),
+
+            Some("o") | Some("g") => {
+                if !meshes.last().unwrap().indices.is_empty() {
+                    meshes.push(MeshData::default());
+                    vertices.clear();
+                }
+                meshes.last_mut().unwrap().name = words.collect::<Vec<_>>().join(" ");
+            }
+
+            Some("f") => {
+                let mesh = meshes.last_mut().unwrap();
+                let mut face = Vec::new();
+
+                for word in words {
+                    let mut parts = word.split('/');
+                    let key = (
+                        obj_index(parts.next(), positions.len(), n)?,
+                        obj_index(parts.next(), tex_coords.len(), n)?,
+                        obj_index(parts.next(), normals.len(), n)?,
+                    );
+
+                    let position = match key.0 {
+                        Some(i) => positions[i],
+                        None => bail!("line {}: vertex without a position", n),
+                    };
+
+                    let index = *vertices.entry(key).or_insert_with(|| {
+                        mesh.vertices.push(MeshVertex {
+                            position,
+                            tex_coords: key.1.map_or([0.; 2], |i| tex_coords[i]),
+                            normal: key.2.map_or([0.; 3], |i| normals[i]),
+                        });
+                        mesh.vertices.len() as u32 - 1
+                    });
+                    face.push(index);
+                }
+
+                ensure!(
+                    face.len() >= 3,
+                    "line {}: face with less than 3 vertices",
+                    n
+                );
+                for i in 1..face.len() - 1 {
+                    mesh.indices
+                        .extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                }
+            }
+
+            // Comments, materials and smoothing groups
+            _ => {}
+        }
+    }
+
+    meshes.retain(|m| !m.indices.is_empty());
+    Ok(meshes)
+}
+
+/// The first `N` floats of a line, ignoring the optional ones after them.
+fn obj_floats<const N: usize>(mut words: std::str::SplitWhitespace, n: usize) -> Result<[f32; N]> {
+    let mut out = [0.; N];
+    for x in out.iter_mut() {
+        match words.next().map(str::parse) {
+            Some(Ok(f)) => *x = f,
+            _ => bail!("line {}: expected {} numbers", n, N),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Resolves a 1-based or negative, relative, index into a list of `len` elements.
+fn obj_index(word: Option<&str>, len: usize, n: usize) -> Result<Option<usize>> {
+    let i: i64 = match word {
+        None | Some("") => return Ok(None),
+        Some(word) => word.parse()?,
+    };
+
+    let index = if i < 0 { len as i64 + i } else { i - 1 };
+    ensure!(
+        (0..len as i64).contains(&index),
+        "line {}: index {} out of bounds",
+        n,
+        i
+    );
+
+    Ok(Some(index as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shrinkwraprs::*;
+    use std::{fs, thread, time::Duration};
+
+    /// Loads text files, failing on files containing "fail".
+    struct TextLoader;
+
+    impl AssetLoader for TextLoader {
+        type Asset = String;
+
+        fn extensions(&self) -> &[&'static str] {
+            &["txt"]
+        }
+
+        fn load(&self, bytes: &[u8], _: &Path) -> Result<String> {
+            let text = String::from_utf8(bytes.to_vec())?;
+            ensure!(!text.contains("fail"), "asked to fail");
+            Ok(text)
+        }
+    }
+
+    /// Assets in a temporary directory, deleted once dropped.
+    #[derive(Shrinkwrap)]
+    #[shrinkwrap(mutable)]
+    struct TempAssets(Assets);
+
+    impl Drop for TempAssets {
+        fn drop(&mut self) {
+            fs::remove_dir_all(self.0.root()).ok();
+        }
+    }
+
+    fn assets(name: &str) -> TempAssets {
+        let dir = std::env::temp_dir().join(format!("assets-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut assets = Assets::new(dir, &JobPool::new(1).unwrap());
+        assets.add_loader(TextLoader);
+        TempAssets(assets)
+    }
+
+    fn write(assets: &Assets, path: &str, text: &str) {
+        fs::write(assets.root().join(path), text).unwrap();
+    }
+
+    /// Polls until something happens.
+    fn wait(assets: &mut Assets) -> Vec<AssetEvent> {
+        for _ in 0..5000 {
+            let events = assets.poll();
+            if !events.is_empty() {
+                return events;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        panic!("no asset event");
+    }
+
+    #[test]
+    fn handle_reuse() {
+        let mut assets = assets("reuse");
+        write(&assets, "a.txt", "a");
+
+        let a = assets.load::<String, _>("a.txt").unwrap();
+        let again = assets.load::<String, _>("./b/../a.txt").unwrap();
+        assert_eq!(a, again);
+        assert_eq!(assets.len(), 1);
+        assert_eq!(assets.path(&a), Some(Path::new("a.txt")));
+
+        // No loader for the extension nor for the type
+        assert!(assets.load::<String, _>("a.png").is_err());
+        assert!(assets.load::<SpirV, _>("a.txt").is_err());
+
+        assert_eq!(wait(&mut assets), vec![AssetEvent::Loaded(a.id())]);
+        assert_eq!(assets.get(&again).map(String::as_str), Some("a"));
+
+        // Freed once every handle is dropped
+        let id = a.id();
+        drop(a);
+        assert!(assets.poll().is_empty());
+        drop(again);
+        assert_eq!(assets.poll(), vec![AssetEvent::Removed(id)]);
+        assert!(assets.is_empty());
+
+        let a = assets.load::<String, _>("a.txt").unwrap();
+        assert_ne!(a.id(), id);
+    }
+
+    #[test]
+    fn foreign_handle() {
+        let mut assets = assets("foreign");
+        let mut other = self::assets("foreign-other");
+
+        other.insert(String::from("other"));
+        let foreign = other.insert(String::from("foreign"));
+        assets.insert(String::from("own"));
+
+        assert_eq!(assets.load_state(&foreign), LoadState::Unknown);
+        assert!(!assets.is_loaded(&foreign));
+        assert_eq!(assets.get(&foreign), None);
+        assert_eq!(assets.error(&foreign), None);
+        assert_eq!(assets.path(&foreign), None);
+    }
+
+    #[test]
+    fn failed_load() {
+        let mut assets = assets("failed");
+        write(&assets, "fail.txt", "fail");
+
+        let failed = assets.load::<String, _>("fail.txt").unwrap();
+        let missing = assets.load::<String, _>("missing.txt").unwrap();
+        assert_eq!(assets.load_state(&failed), LoadState::Loading);
+
+        let mut events = wait(&mut assets);
+        if events.len() < 2 {
+            events.extend(wait(&mut assets));
+        }
+        events.sort_by_key(AssetEvent::id);
+        assert_eq!(
+            events,
+            vec![
+                AssetEvent::Failed(failed.id()),
+                AssetEvent::Failed(missing.id())
+            ]
+        );
+
+        assert_eq!(assets.load_state(&failed), LoadState::Failed);
+        assert_eq!(assets.get(&failed), None);
+        assert!(assets.error(&failed).unwrap().contains("asked to fail"));
+        assert!(assets.error(&missing).is_some());
+    }
+
+    #[test]
+    fn reload() {
+        let mut assets = assets("reload");
+        write(&assets, "a.txt", "one");
+
+        let a = assets.load::<String, _>("a.txt").unwrap();
+        assert_eq!(wait(&mut assets), vec![AssetEvent::Loaded(a.id())]);
+
+        write(&assets, "a.txt", "two");
+        assets.reload(&a).unwrap();
+        assert_eq!(wait(&mut assets), vec![AssetEvent::Reloaded(a.id())]);
+        assert_eq!(assets.get(&a).map(String::as_str), Some("two"));
+
+        // A failed reload keeps the previous value
+        write(&assets, "a.txt", "fail");
+        assets.reload(&a).unwrap();
+        assert_eq!(wait(&mut assets), vec![AssetEvent::Failed(a.id())]);
+        assert!(assets.is_loaded(&a));
+        assert_eq!(assets.get(&a).map(String::as_str), Some("two"));
+        assert!(assets.error(&a).is_some());
+
+        let inserted = assets.insert(String::from("three"));
+        assert!(assets.reload(&inserted).is_err());
+    }
+
+    #[cfg(feature = "hot-reload")]
+    #[test]
+    fn hot_reload() {
+        let mut assets = assets("hot-reload");
+        fs::create_dir_all(assets.root().join("text")).unwrap();
+        write(&assets, "text/a.txt", "one");
+        assets.watch().unwrap();
+
+        let a = assets.load::<String, _>("./text/a.txt").unwrap();
+        assert_eq!(wait(&mut assets), vec![AssetEvent::Loaded(a.id())]);
+
+        write(&assets, "text/a.txt", "two");
+        assert_eq!(wait(&mut assets), vec![AssetEvent::Reloaded(a.id())]);
+        assert_eq!(assets.get(&a).map(String::as_str), Some("two"));
+    }
+
+    #[test]
+    fn obj_meshes() {
+        let meshes = parse_obj(
+            "# a quad and a triangle
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 1
+            vn 0 0 1
+            o quad
+            f 1/1/1 2/1/1 3/2/1 4/2/1
+            o tri
+            usemtl stone
+            f -4//1 -3//1 -2//1",
+        )
+        .unwrap();
+
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].name, "quad");
+        assert_eq!(meshes[0].vertices.len(), 4);
+        assert_eq!(meshes[0].indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(
+            meshes[0].vertices[2],
+            MeshVertex {
+                position: [1., 1., 0.],
+                tex_coords: [1., 1.],
+                normal: [0., 0., 1.],
+            }
+        );
+
+        assert_eq!(meshes[1].name, "tri");
+        assert_eq!(meshes[1].indices, vec![0, 1, 2]);
+        assert_eq!(meshes[1].vertices[0].tex_coords, [0., 0.]);
+
+        assert!(parse_obj("v 0 0 0\nf 1 2 3").is_err());
+        assert!(parse_obj("v 0 0\nf 1 1 1").is_err());
+        assert!(parse_obj("v 0 0 0\nf 1 1").is_err());
+    }
+}
@@ -0,0 +1,549 @@
+use crate::{chunk::UnitBytes, world::edit::Rotation};
+use anyhow::{bail, ensure, Result};
+use std::collections::HashMap;
+
+/// No. of distinct `BlockState`s.
+const MAX_STATES: u64 = u16::MAX as u64 + 1;
+
+/// Index of a block type in a `BlockRegistry`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u16);
+
+/// A block type along with the values of all its properties,
+/// packed in a single number usable as a chunk `Unit`.
+///
+/// State `0` is the default state of the first registered block,
+/// which should usually be air.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockState(pub u16);
+
+impl UnitBytes for BlockState {
+    const SIZE: usize = 2;
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        self.0.write_bytes(out);
+    }
+
+    fn read_bytes(bytes: &[u8]) -> Self {
+        Self(u16::read_bytes(bytes))
+    }
+}
+
+/// Kind of values a property can hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum PropertyKind {
+    Bool,
+    /// An integer in `min..=max`.
+    Int {
+        min: i32,
+        max: i32,
+    },
+    /// One of several named values.
+    Enum(Vec<String>),
+}
+
+/// A value of a property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i32),
+    /// Index of an enum value.
+    Enum(usize),
+}
+
+/// A named property of a block type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct Property {
+    pub name: String,
+    pub kind: PropertyKind,
+}
+
+impl Property {
+    pub fn bool(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: PropertyKind::Bool,
+        }
+    }
+
+    pub fn int(name: &str, min: i32, max: i32) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: PropertyKind::Int {
+                min: min.min(max),
+                max: max.max(min),
+            },
+        }
+    }
+
+    pub fn enumeration(name: &str, values: &[&str]) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: PropertyKind::Enum(values.iter().map(|&v| v.to_owned()).collect()),
+        }
+    }
+
+    /// No. of values this property can hold.
+    pub fn cardinality(&self) -> u64 {
+        match &self.kind {
+            PropertyKind::Bool => 2,
+            PropertyKind::Int { min, max } => (*max as i64 - *min as i64 + 1) as u64,
+            PropertyKind::Enum(values) => values.len() as u64,
+        }
+    }
+
+    /// Index of an enum value by name.
+    pub fn enum_index(&self, value: &str) -> Option<usize> {
+        match &self.kind {
+            PropertyKind::Enum(values) => values.iter().position(|v| v == value),
+            _ => None,
+        }
+    }
+
+    fn index_of(&self, value: PropertyValue) -> Option<u32> {
+        match (&self.kind, value) {
+            (PropertyKind::Bool, PropertyValue::Bool(b)) => Some(b as u32),
+            (PropertyKind::Int { min, max }, PropertyValue::Int(i))
+                if (*min..=*max).contains(&i) =>
+            {
+                Some((i as i64 - *min as i64) as u32)
+            }
+            (PropertyKind::Enum(values), PropertyValue::Enum(i)) if i < values.len() => {
+                Some(i as u32)
+            }
+            _ => None,
+        }
+    }
+
+    fn value_of(&self, index: u32) -> PropertyValue {
+        match &self.kind {
+            PropertyKind::Bool => PropertyValue::Bool(index != 0),
+            PropertyKind::Int { min, .. } => {
+                PropertyValue::Int((*min as i64 + index as i64) as i32)
+            }
+            PropertyKind::Enum(_) => PropertyValue::Enum(index as usize),
+        }
+    }
+}
+
+/// Declares a block type and the schema of its states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockType {
+    pub name: String,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub properties: Vec<Property>,
+    /// Enum property holding a horizontal facing in clockwise order,
+    /// e.g. `["north", "east", "south", "west"]`, used for rotations.
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub facing: Option<String>,
+}
+
+impl BlockType {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            properties: Vec::new(),
+            facing: None,
+        }
+    }
+
+    pub fn with(mut self, property: Property) -> Self {
+        self.properties.push(property);
+        self
+    }
+
+    /// Adds a facing property with four clockwise values,
+    /// the first one being the unrotated orientation.
+    pub fn with_facing(mut self, name: &str, values: [&str; 4]) -> Self {
+        self.properties.push(Property::enumeration(name, &values));
+        self.facing = Some(name.to_owned());
+        self
+    }
+
+    /// No. of states of this block, saturating at `u64::MAX`.
+    pub fn state_count(&self) -> u64 {
+        self.properties
+            .iter()
+            .fold(1, |count, p| count.saturating_mul(p.cardinality()))
+    }
+}
+
+#[derive(Debug)]
+struct Registered {
+    def: BlockType,
+    first: u16,
+    /// Mixed radix stride of each property.
+    strides: Vec<u32>,
+    by_name: HashMap<String, usize>,
+}
+
+/// A resource holding every block type and their states.
+///
+/// Every lookup goes through tables indexed by the state,
+/// so none of them depend on the number of blocks or properties.
+#[derive(Debug, Default)]
+pub struct BlockRegistry {
+    blocks: Vec<Registered>,
+    by_name: HashMap<String, BlockId>,
+    /// Block of every state.
+    state_block: Vec<BlockId>,
+    /// Every state rotated by 0, 1, 2 and 3 clockwise quarter turns.
+    state_rotations: Vec<[BlockState; 4]>,
+    /// Rotation a mesher should apply to the model of every state.
+    state_model_rotation: Vec<Rotation>,
+}
+
+impl BlockRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a block type and all of its states.
+    pub fn register(&mut self, def: BlockType) -> Result<BlockId> {
+        ensure!(
+            !self.by_name.contains_key(&def.name),
+            "block `{}` is already registered",
+            def.name
+        );
+
+        for (i, p) in def.properties.iter().enumerate() {
+            ensure!(
+                def.properties[..i].iter().all(|q| q.name != p.name),
+                "property `{}` of block `{}` is declared twice",
+                p.name,
+                def.name
+            );
+            ensure!(
+                p.cardinality() > 0,
+                "property `{}` of block `{}` has no values",
+                p.name,
+                def.name
+            );
+            ensure!(
+                p.cardinality() <= MAX_STATES,
+                "property `{}` of block `{}` has {} values, more than the {} states of all blocks",
+                p.name,
+                def.name,
+                p.cardinality(),
+                MAX_STATES
+            );
+        }
+
+        let count = def.state_count();
+        let first = self.state_block.len() as u64;
+        if count > MAX_STATES - first {
+            bail!(
+                "too many block states to register `{}`, it has {} states and {} are left",
+                def.name,
+                count,
+                MAX_STATES - first
+            );
+        }
+
+        let facing = match &def.facing {
+            Some(name) => match def.properties.iter().position(|p| &p.name == name) {
+                Some(i) if def.properties[i].cardinality() == 4 => Some(i),
+                _ => bail!("`{}` facing must be an enum property of 4 values", def.name),
+            },
+            None => None,
+        };
+
+        let mut strides = Vec::with_capacity(def.properties.len());
+        let mut stride = 1;
+        for p in def.properties.iter() {
+            strides.push(stride);
+            stride *= p.cardinality() as u32;
+        }
+
+        let id = BlockId(self.blocks.len() as u16);
+        let first = first as u16;
+
+        for local in 0..count as u32 {
+            self.state_block.push(id);
+
+            match facing {
+                Some(f) => {
+                    let quarter = (local / strides[f]) % 4;
+                    let base = local - quarter * strides[f];
+                    let rotated = |q: u32| {
+                        BlockState(first + (base + ((quarter + q) % 4) * strides[f]) as u16)
+                    };
+
+                    self.state_rotations
+                        .push([rotated(0), rotated(1), rotated(2), rotated(3)]);
+                    self.state_model_rotation
+                        .push(Rotation::from_quarters(quarter as u8));
+                }
+
+                None => {
+                    let state = BlockState(first + local as u16);
+                    self.state_rotations.push([state; 4]);
+                    self.state_model_rotation.push(Rotation::None);
+                }
+            }
+        }
+
+        let by_name = def
+            .properties
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.name.clone(), i))
+            .collect();
+
+        self.by_name.insert(def.name.clone(), id);
+        self.blocks.push(Registered {
+            def,
+            first,
+            strides,
+            by_name,
+        });
+
+        Ok(id)
+    }
+
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn block(&self, id: BlockId) -> Option<&BlockType> {
+        self.blocks.get(id.0 as usize).map(|r| &r.def)
+    }
+
+    /// State with the first value of every property.
+    pub fn default_state(&self, id: BlockId) -> Option<BlockState> {
+        self.blocks.get(id.0 as usize).map(|r| BlockState(r.first))
+    }
+
+    /// Block type of a state.
+    /// ## Panics
+    /// If the state was not produced by this registry.
+    pub fn block_of(&self, state: BlockState) -> BlockId {
+        self.state_block[state.0 as usize]
+    }
+
+    /// Name of the block type of a state.
+    /// ## Panics
+    /// If the state was not produced by this registry.
+    pub fn name_of(&self, state: BlockState) -> &str {
+        &self.blocks[self.block_of(state).0 as usize].def.name
+    }
+
+    /// Value of a property of a state.
+    /// ## Panics
+    /// If the state was not produced by this registry.
+    pub fn get(&self, state: BlockState, property: &str) -> Option<PropertyValue> {
+        let r = &self.blocks[self.block_of(state).0 as usize];
+        let i = *r.by_name.get(property)?;
+        let local = (state.0 - r.first) as u32;
+
+        Some(
+            r.def.properties[i]
+                .value_of((local / r.strides[i]) % r.def.properties[i].cardinality() as u32),
+        )
+    }
+
+    /// Name of the value of an enum property of a state.
+    /// ## Panics
+    /// If the state was not produced by this registry.
+    pub fn get_enum(&self, state: BlockState, property: &str) -> Option<&str> {
+        let r = &self.blocks[self.block_of(state).0 as usize];
+        let p = &r.def.properties[*r.by_name.get(property)?];
+
+        match (&p.kind, self.get(state, property)?) {
+            (PropertyKind::Enum(values), PropertyValue::Enum(i)) => Some(&values[i]),
+            _ => None,
+        }
+    }
+
+    /// The same state with a property changed,
+    /// `None` if the block doesn't have the property or the value is invalid.
+    /// ## Panics
+    /// If the state was not produced by this registry.
+    pub fn with(
+        &self,
+        state: BlockState,
+        property: &str,
+        value: PropertyValue,
+    ) -> Option<BlockState> {
+        let r = &self.blocks[self.block_of(state).0 as usize];
+        let i = *r.by_name.get(property)?;
+        let p = &r.def.properties[i];
+
+        let new = p.index_of(value)?;
+        let local = (state.0 - r.first) as u32;
+        let old = (local / r.strides[i]) % p.cardinality() as u32;
+
+        Some(BlockState(
+            r.first + (local - old * r.strides[i] + new * r.strides[i]) as u16,
+        ))
+    }
+
+    /// The state of the block after rotating it around the Y axis.
+    /// ## Panics
+    /// If the state was not produced by this registry.
+    pub fn rotate(&self, state: BlockState, rotation: Rotation) -> BlockState {
+        self.state_rotations[state.0 as usize][rotation.quarters() as usize]
+    }
+
+    /// Rotation a mesher should apply to the block's model.
+    /// ## Panics
+    /// If the state was not produced by this registry.
+    pub fn model_rotation(&self, state: BlockState) -> Rotation {
+        self.state_model_rotation[state.0 as usize]
+    }
+
+    /// No. of states of every registered block.
+    pub fn state_count(&self) -> usize {
+        self.state_block.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> (BlockRegistry, BlockId, BlockId) {
+        let mut registry = BlockRegistry::new();
+        registry.register(BlockType::new("air")).unwrap();
+
+        let stairs = BlockType::new("stairs")
+            .with(Property::bool("waterlogged"))
+            .with_facing("facing", ["north", "east", "south", "west"])
+            .with(Property::int("age", -1, 1));
+        let stairs = registry.register(stairs).unwrap();
+
+        let sign = BlockType::new("sign").with(Property::int("rotation", 0, 15));
+        let sign = registry.register(sign).unwrap();
+
+        (registry, stairs, sign)
+    }
+
+    #[test]
+    fn state_packing() {
+        let (registry, stairs, sign) = registry();
+        assert_eq!(registry.state_count(), 1 + 2 * 4 * 3 + 16);
+
+        let state = registry.default_state(stairs).unwrap();
+        assert_eq!(state, BlockState(1));
+        assert_eq!(registry.name_of(state), "stairs");
+        assert_eq!(registry.get(state, "age"), Some(PropertyValue::Int(-1)));
+        assert_eq!(registry.get_enum(state, "facing"), Some("north"));
+
+        let state = registry
+            .with(state, "age", PropertyValue::Int(1))
+            .and_then(|s| registry.with(s, "waterlogged", PropertyValue::Bool(true)))
+            .and_then(|s| registry.with(s, "facing", PropertyValue::Enum(2)))
+            .unwrap();
+
+        assert_eq!(registry.block_of(state), stairs);
+        assert_eq!(registry.get(state, "age"), Some(PropertyValue::Int(1)));
+        assert_eq!(
+            registry.get(state, "waterlogged"),
+            Some(PropertyValue::Bool(true))
+        );
+        assert_eq!(registry.get_enum(state, "facing"), Some("south"));
+
+        // Invalid values and unknown properties
+        assert_eq!(registry.with(state, "age", PropertyValue::Int(2)), None);
+        assert_eq!(registry.with(state, "age", PropertyValue::Bool(true)), None);
+        assert_eq!(registry.with(state, "color", PropertyValue::Int(0)), None);
+
+        let last = registry
+            .with(
+                registry.default_state(sign).unwrap(),
+                "rotation",
+                PropertyValue::Int(15),
+            )
+            .unwrap();
+        assert_eq!(last.0 as usize, registry.state_count() - 1);
+        assert_eq!(registry.block_of(last), sign);
+    }
+
+    #[test]
+    fn rotations() {
+        let (registry, stairs, sign) = registry();
+        let north = registry
+            .with(
+                registry.default_state(stairs).unwrap(),
+                "age",
+                PropertyValue::Int(0),
+            )
+            .unwrap();
+
+        let east = registry.rotate(north, Rotation::Cw90);
+        assert_eq!(registry.get_enum(east, "facing"), Some("east"));
+        assert_eq!(registry.get(east, "age"), Some(PropertyValue::Int(0)));
+        assert_eq!(registry.model_rotation(east), Rotation::Cw90);
+        assert_eq!(registry.model_rotation(north), Rotation::None);
+
+        let west = registry.rotate(east, Rotation::Cw180);
+        assert_eq!(registry.get_enum(west, "facing"), Some("west"));
+        assert_eq!(registry.model_rotation(west), Rotation::Cw270);
+        assert_eq!(registry.rotate(west, Rotation::Cw90), north);
+
+        // Blocks without facing don't rotate
+        let sign = registry.default_state(sign).unwrap();
+        assert_eq!(registry.rotate(sign, Rotation::Cw90), sign);
+        assert_eq!(registry.model_rotation(sign), Rotation::None);
+    }
+
+    #[test]
+    fn full_range_int() {
+        let full = Property::int("value", i32::MIN, i32::MAX);
+        assert_eq!(full.cardinality(), 1 << 32);
+        assert_eq!(full.index_of(PropertyValue::Int(i32::MAX)), Some(u32::MAX));
+
+        let mut registry = BlockRegistry::new();
+        let error = registry
+            .register(BlockType::new("counter").with(full))
+            .unwrap_err();
+        assert!(error.to_string().contains("4294967296 values"));
+
+        let wide = Property::int("value", i32::MIN, i32::MIN + 7);
+        let id = registry
+            .register(BlockType::new("counter").with(wide))
+            .unwrap();
+        let state = registry.default_state(id).unwrap();
+        let state = registry
+            .with(state, "value", PropertyValue::Int(i32::MIN + 7))
+            .unwrap();
+        assert_eq!(
+            registry.get(state, "value"),
+            Some(PropertyValue::Int(i32::MIN + 7))
+        );
+    }
+
+    #[test]
+    fn too_many_states() {
+        let mut registry = BlockRegistry::new();
+        let big = BlockType::new("big")
+            .with(Property::int("a", 0, 65535))
+            .with(Property::int("b", 0, 65535))
+            .with(Property::int("c", 0, 65535))
+            .with(Property::int("d", 0, 65535))
+            .with(Property::int("e", 0, 65535));
+
+        assert_eq!(big.state_count(), u64::MAX);
+        assert!(registry.register(big).is_err());
+        assert!(registry
+            .register(BlockType::new("empty").with(Property::enumeration("e", &[])))
+            .is_err());
+        assert_eq!(registry.state_count(), 0);
+    }
+
+    #[test]
+    fn duplicate_properties() {
+        let mut registry = BlockRegistry::new();
+        let twice = BlockType::new("lamp")
+            .with(Property::bool("lit"))
+            .with(Property::int("lit", 0, 15));
+
+        let error = registry.register(twice).unwrap_err();
+        assert!(error.to_string().contains("`lit`"), "{}", error);
+        assert_eq!(registry.id("lamp"), None);
+        assert_eq!(registry.state_count(), 0);
+    }
+}
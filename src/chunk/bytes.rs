@@ -0,0 +1,117 @@
+use super::Unit;
+use anyhow::{ensure, Result};
+
+/// A `Unit` with a fixed-size little endian byte representation,
+/// used by every binary encoding of chunk data.
+pub trait UnitBytes: Unit {
+    /// No. of bytes written per unit.
+    const SIZE: usize;
+
+    /// Appends exactly `SIZE` bytes to `out`.
+    fn write_bytes(&self, out: &mut Vec<u8>);
+
+    /// Reads a unit back from exactly `SIZE` bytes.
+    fn read_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_unit_bytes {
+    ($($t:ty),*) => {
+        $(
+            impl UnitBytes for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn write_bytes(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_bytes(bytes: &[u8]) -> Self {
+                    let mut buf = [0; std::mem::size_of::<$t>()];
+                    buf.copy_from_slice(&bytes[..Self::SIZE]);
+                    Self::from_le_bytes(buf)
+                }
+            }
+        )*
+    };
+}
+
+impl_unit_bytes!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl UnitBytes for bool {
+    const SIZE: usize = 1;
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn read_bytes(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+}
+
+/// Appends `val` as an LEB128 variable length integer.
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        out.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+/// A cursor over an encoded byte slice.
+#[derive(Debug)]
+pub(crate) struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(
+            self.bytes.len() - self.pos >= len,
+            "unexpected end of data, needed {} more bytes",
+            len
+        );
+
+        let out = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(out)
+    }
+
+    /// Takes every byte left.
+    pub fn rest(&mut self) -> &'a [u8] {
+        let out = &self.bytes[self.pos.min(self.bytes.len())..];
+        self.pos = self.bytes.len();
+        out
+    }
+
+    pub fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn varint(&mut self) -> Result<u64> {
+        let mut val = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            val |= ((b & 0x7f) as u64) << shift;
+
+            if b & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+
+        anyhow::bail!("varint is longer than 64 bits")
+    }
+
+    pub fn unit<T: UnitBytes>(&mut self) -> Result<T> {
+        Ok(T::read_bytes(self.take(T::SIZE)?))
+    }
+}
@@ -0,0 +1,338 @@
+use super::{
+    bytes::{write_varint, ByteReader},
+    Accessor, Chunk, Unit, UnitBytes,
+};
+use anyhow::{bail, ensure, Result};
+use std::slice::Iter;
+
+/// A single voxel change inside a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoxelChange<T: Unit> {
+    /// Flat index in `Accessor` order.
+    pub index: usize,
+    pub before: T,
+    pub after: T,
+}
+
+impl<T: Unit> VoxelChange<T> {
+    /// The same change going the other way.
+    pub fn inverse(&self) -> Self {
+        Self {
+            index: self.index,
+            before: self.after,
+            after: self.before,
+        }
+    }
+}
+
+/// What changed in a chunk, stored as a sparse list
+/// of changes sorted by index, with at most one per voxel.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChunkDelta<T: Unit> {
+    changes: Vec<VoxelChange<T>>,
+}
+
+impl<T: Unit> Default for ChunkDelta<T> {
+    fn default() -> Self {
+        Self {
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl<T: Unit> ChunkDelta<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Computes the delta that turns `old` into `new`.
+    pub fn between<A: Accessor, const N: usize>(
+        old: &Chunk<A, T, N>,
+        new: &Chunk<A, T, N>,
+    ) -> Self {
+        let changes = old
+            .data
+            .iter()
+            .zip(new.data.iter())
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(index, (&before, &after))| VoxelChange {
+                index,
+                before,
+                after,
+            })
+            .collect();
+
+        Self { changes }
+    }
+
+    /// Records a change, merging it with an earlier change of the same voxel.
+    /// A voxel that ends up back at its original value is dropped from the delta.
+    pub fn record(&mut self, index: usize, before: T, after: T) {
+        match self.changes.binary_search_by_key(&index, |c| c.index) {
+            Ok(i) => {
+                if self.changes[i].before == after {
+                    self.changes.remove(i);
+                } else {
+                    self.changes[i].after = after;
+                }
+            }
+
+            Err(i) => {
+                if before != after {
+                    self.changes.insert(
+                        i,
+                        VoxelChange {
+                            index,
+                            before,
+                            after,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Writes `value` at `pos` (YXZ) in `chunk` while recording the change.
+    /// Returns the previous value.
+    pub fn set<A: Accessor, const N: usize>(
+        &mut self,
+        chunk: &mut Chunk<A, T, N>,
+        pos: [usize; 3],
+        value: T,
+    ) -> T {
+        let index = A::to_index(pos);
+        let before = std::mem::replace(&mut chunk.data[index], value);
+
+        self.record(index, before, value);
+        before
+    }
+
+    /// Moves `chunk` forward through this delta.
+    pub fn apply<A: Accessor, const N: usize>(&self, chunk: &mut Chunk<A, T, N>) {
+        self.changes
+            .iter()
+            .for_each(|c| chunk.data[c.index] = c.after);
+    }
+
+    /// Moves `chunk` back through this delta.
+    pub fn revert<A: Accessor, const N: usize>(&self, chunk: &mut Chunk<A, T, N>) {
+        self.changes
+            .iter()
+            .for_each(|c| chunk.data[c.index] = c.before);
+    }
+
+    /// A delta undoing this one.
+    pub fn inverse(&self) -> Self {
+        Self {
+            changes: self.changes.iter().map(VoxelChange::inverse).collect(),
+        }
+    }
+
+    /// Composes this delta with one applied right after it,
+    /// producing a single delta with the combined effect.
+    pub fn then(&self, next: &Self) -> Self {
+        let mut out = self.clone();
+        out.append(next);
+        out
+    }
+
+    /// In place version of [`then`](ChunkDelta::then).
+    pub fn append(&mut self, next: &Self) {
+        next.changes
+            .iter()
+            .for_each(|c| self.record(c.index, c.before, c.after));
+    }
+
+    pub fn iter(&self) -> Iter<'_, VoxelChange<T>> {
+        self.changes.iter()
+    }
+
+    /// Positions (YXZ) of every changed voxel.
+    pub fn positions<A: Accessor>(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.changes.iter().map(|c| A::from_index(c.index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+}
+
+impl<T: UnitBytes> ChunkDelta<T> {
+    /// Compact binary encoding, a varint count followed by
+    /// varint index gaps and the before/after bytes of every change.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(1 + self.changes.len() * (2 + 2 * T::SIZE));
+        write_varint(&mut out, self.changes.len() as u64);
+
+        let mut last = 0;
+        for c in self.changes.iter() {
+            write_varint(&mut out, (c.index - last) as u64);
+            c.before.write_bytes(&mut out);
+            c.after.write_bytes(&mut out);
+            last = c.index;
+        }
+
+        out
+    }
+
+    /// Decodes a delta produced by [`encode`](ChunkDelta::encode),
+    /// for chunks of `N` units, rejecting indices out of them.
+    pub fn decode<const N: usize>(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader::new(bytes);
+        let len = reader.varint()? as usize;
+
+        let mut changes = Vec::with_capacity(len.min(bytes.len()));
+        let mut last = 0usize;
+
+        for i in 0..len {
+            let gap = reader.varint()? as usize;
+            ensure!(
+                i == 0 || gap > 0,
+                "delta indices must be strictly increasing"
+            );
+
+            let index = match last.checked_add(gap) {
+                Some(index) if index < N => index,
+                _ => bail!("delta index out of a chunk of {} units", N),
+            };
+            let before = reader.unit()?;
+            let after = reader.unit()?;
+
+            changes.push(VoxelChange {
+                index,
+                before,
+                after,
+            });
+            last = index;
+        }
+
+        ensure!(reader.is_empty(), "trailing bytes after chunk delta");
+        Ok(Self { changes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    type TestChunk = Chunk<Dim, u16, 64>;
+
+    fn edited() -> (TestChunk, TestChunk, ChunkDelta<u16>) {
+        let old = TestChunk::default();
+        let mut new = old;
+        let mut delta = ChunkDelta::new();
+
+        delta.set(&mut new, [0, 0, 0], 7);
+        delta.set(&mut new, [3, 2, 1], 300);
+        delta.set(&mut new, [1, 1, 1], 9);
+        delta.set(&mut new, [1, 1, 1], 0);
+
+        (old, new, delta)
+    }
+
+    #[test]
+    fn record_merges_and_drops_noops() {
+        let (old, new, delta) = edited();
+
+        assert_eq!(delta.len(), 2);
+        assert_eq!(delta, ChunkDelta::between(&old, &new));
+    }
+
+    #[test]
+    fn apply_and_revert() {
+        let (old, new, delta) = edited();
+
+        let mut chunk = old;
+        delta.apply(&mut chunk);
+        assert_eq!(chunk, new);
+
+        delta.revert(&mut chunk);
+        assert_eq!(chunk, old);
+
+        delta.inverse().apply(&mut chunk);
+        assert_eq!(chunk, old);
+    }
+
+    #[test]
+    fn then_composes() {
+        let (old, new, first) = edited();
+
+        let mut newer = new;
+        let mut second = ChunkDelta::new();
+        second.set(&mut newer, [0, 0, 0], 0);
+        second.set(&mut newer, [2, 2, 2], 5);
+
+        let both = first.then(&second);
+        assert_eq!(both, ChunkDelta::between(&old, &newer));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let (_, _, delta) = edited();
+
+        let bytes = delta.encode();
+        assert_eq!(ChunkDelta::decode::<64>(&bytes).unwrap(), delta);
+        assert_eq!(
+            ChunkDelta::<u16>::decode::<64>(&ChunkDelta::<u16>::new().encode()).unwrap(),
+            ChunkDelta::new()
+        );
+    }
+
+    #[test]
+    fn decode_rejects_out_of_bounds() {
+        let mut delta = ChunkDelta::new();
+        delta.record(63, 0u16, 1);
+
+        let bytes = delta.encode();
+        assert!(ChunkDelta::<u16>::decode::<64>(&bytes).is_ok());
+        assert!(ChunkDelta::<u16>::decode::<63>(&bytes).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_overflow() {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, 2);
+        for _ in 0..2 {
+            write_varint(&mut bytes, u64::MAX);
+            bytes.extend_from_slice(&[0, 0, 1, 0]);
+        }
+
+        assert!(ChunkDelta::<u16>::decode::<64>(&bytes).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_malformed() {
+        let (_, _, delta) = edited();
+        let bytes = delta.encode();
+
+        assert!(ChunkDelta::<u16>::decode::<64>(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(ChunkDelta::<u16>::decode::<64>(&trailing).is_err());
+
+        let mut repeated = Vec::new();
+        write_varint(&mut repeated, 2);
+        for _ in 0..2 {
+            write_varint(&mut repeated, 0);
+            repeated.extend_from_slice(&[0, 0, 1, 0]);
+        }
+        assert!(ChunkDelta::<u16>::decode::<64>(&repeated).is_err());
+    }
+}
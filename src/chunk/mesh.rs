@@ -0,0 +1,139 @@
+use super::{Accessor, Chunk, Unit};
+
+/// One of the six faces of a voxel or chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::PosX,
+        Face::NegX,
+        Face::PosY,
+        Face::NegY,
+        Face::PosZ,
+        Face::NegZ,
+    ];
+
+    /// Outward direction in XYZ order.
+    pub fn normal(&self) -> [i32; 3] {
+        use Face::*;
+        match self {
+            PosX => [1, 0, 0],
+            NegX => [-1, 0, 0],
+            PosY => [0, 1, 0],
+            NegY => [0, -1, 0],
+            PosZ => [0, 0, 1],
+            NegZ => [0, 0, -1],
+        }
+    }
+
+    pub fn opposite(&self) -> Self {
+        use Face::*;
+        match self {
+            PosX => NegX,
+            NegX => PosX,
+            PosY => NegY,
+            NegY => PosY,
+            PosZ => NegZ,
+            NegZ => PosZ,
+        }
+    }
+
+    /// Counter-clockwise corners of this face on a unit cube, seen from outside.
+    fn corners(&self) -> [[f32; 3]; 4] {
+        use Face::*;
+        match self {
+            PosX => [[1., 0., 0.], [1., 1., 0.], [1., 1., 1.], [1., 0., 1.]],
+            NegX => [[0., 0., 0.], [0., 0., 1.], [0., 1., 1.], [0., 1., 0.]],
+            PosY => [[0., 1., 0.], [0., 1., 1.], [1., 1., 1.], [1., 1., 0.]],
+            NegY => [[0., 0., 0.], [1., 0., 0.], [1., 0., 1.], [0., 0., 1.]],
+            PosZ => [[0., 0., 1.], [1., 0., 1.], [1., 1., 1.], [0., 1., 1.]],
+            NegZ => [[0., 0., 0.], [0., 1., 0.], [1., 1., 0.], [1., 0., 0.]],
+        }
+    }
+}
+
+/// Returns the neighbouring position (YXZ) through `face`,
+/// or `None` when it falls outside of the chunk.
+pub fn neighbour<A: Accessor>([y, x, z]: [usize; 3], face: Face) -> Option<[usize; 3]> {
+    let [dx, dy, dz] = face.normal();
+    let step = |v: usize, d: i32| {
+        let v = v as i32 + d;
+        if v >= 0 && (v as usize) < A::SIDE_LEN {
+            Some(v as usize)
+        } else {
+            None
+        }
+    };
+
+    Some([step(y, dy)?, step(x, dx)?, step(z, dz)?])
+}
+
+/// CPU side geometry of a chunk in local coordinates,
+/// faces shared by two opaque voxels are culled.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMesh {
+    /// Meshes every voxel for which `opaque` returns true.
+    pub fn build<A, T, F, const N: usize>(chunk: &Chunk<A, T, N>, opaque: F) -> Self
+    where
+        A: Accessor,
+        T: Unit,
+        F: Fn(&T) -> bool,
+    {
+        let mut mesh = Self::default();
+
+        for (i, t) in chunk.iter_slice().enumerate() {
+            if !opaque(t) {
+                continue;
+            }
+
+            let pos = A::from_index(i);
+            for &face in Face::ALL.iter() {
+                let hidden = neighbour::<A>(pos, face).map_or(false, |n| opaque(&chunk[n]));
+
+                if !hidden {
+                    mesh.push_face(pos, face);
+                }
+            }
+        }
+
+        mesh
+    }
+
+    fn push_face(&mut self, [y, x, z]: [usize; 3], face: Face) {
+        let base = self.positions.len() as u32;
+        let [nx, ny, nz] = face.normal();
+        let normal = [nx as f32, ny as f32, nz as f32];
+
+        for [cx, cy, cz] in face.corners().iter() {
+            self.positions
+                .push([x as f32 + cx, y as f32 + cy, z as f32 + cz]);
+            self.normals.push(normal);
+        }
+
+        self.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    /// No. of quads in the mesh.
+    pub fn face_count(&self) -> usize {
+        self.indices.len() / 6
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
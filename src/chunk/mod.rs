@@ -1,3 +1,13 @@
+pub(crate) mod bytes;
+pub mod delta;
+pub mod mesh;
+pub mod rle;
+#[cfg(feature = "serialize")]
+mod serialize;
+pub mod visibility;
+
+pub use bytes::UnitBytes;
+
 use cgmath::Point3;
 use std::{
     fmt::Debug,
@@ -106,7 +116,7 @@ impl<A: Accessor, T: Unit, const N: usize> Default for Chunk<A, T, N> {
     fn default() -> Self {
         Self {
             data: [T::default(); N],
-            ..Default::default()
+            state: PhantomData::default(),
         }
     }
 }
@@ -151,7 +161,7 @@ pub trait ChunkWireFrame: ChunkPlots {
 /// An accessor trait is necessary because of a limitation
 /// of min_const_generics where we can't predetermine the Chunk size
 /// of the data type through 'static expressions `struct Chunk<D, T, N * N * N>`
-pub trait Accessor: Clone + Copy + Eq + std::hash::Hash {
+pub trait Accessor: 'static + Clone + Copy + Eq + std::hash::Hash + Send + Sync {
     /// No. of elements in a row/column
     const SIDE_LEN: usize;
     /// No. of elements in a plane
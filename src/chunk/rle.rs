@@ -0,0 +1,215 @@
+//! Run-length encoding of chunks, for network transfer and saves.
+//!
+//! The format starts with a header:
+//! - the format version, a byte
+//! - the `Accessor::SIDE_LEN` of the chunk, a varint
+//! - the `UnitBytes::SIZE` of its units, a varint
+//!
+//! followed by runs of identical units in index order, each one being
+//! a varint length and the unit's bytes, until the chunk is filled.
+use super::{
+    bytes::{write_varint, ByteReader},
+    Accessor, Chunk, UnitBytes,
+};
+use anyhow::{ensure, Result};
+
+/// Version of the format written by [`Chunk::encode_rle`](Chunk::encode_rle).
+pub const RLE_VERSION: u8 = 1;
+
+impl<A: Accessor, T: UnitBytes, const N: usize> Chunk<A, T, N> {
+    /// Encodes the chunk as runs of identical units.
+    pub fn encode_rle(&self) -> Vec<u8> {
+        let mut out = vec![RLE_VERSION];
+        write_varint(&mut out, A::SIDE_LEN as u64);
+        write_varint(&mut out, T::SIZE as u64);
+
+        let mut units = self.data.iter().peekable();
+        while let Some(unit) = units.next() {
+            let mut len = 1;
+            while units.next_if_eq(&unit).is_some() {
+                len += 1;
+            }
+
+            write_varint(&mut out, len);
+            unit.write_bytes(&mut out);
+        }
+
+        out
+    }
+
+    /// Decodes a chunk produced by [`encode_rle`](Chunk::encode_rle),
+    /// failing if it was encoded with other dimensions or units.
+    pub fn decode_rle(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader::new(bytes);
+
+        let version = reader.byte()?;
+        ensure!(
+            version == RLE_VERSION,
+            "unsupported chunk format version {}",
+            version
+        );
+
+        let side = reader.varint()?;
+        ensure!(
+            side == A::SIDE_LEN as u64,
+            "chunk side of {} expected, found {}",
+            A::SIDE_LEN,
+            side
+        );
+
+        let size = reader.varint()?;
+        ensure!(
+            size == T::SIZE as u64,
+            "unit size of {} expected, found {}",
+            T::SIZE,
+            size
+        );
+
+        let mut data = [T::default(); N];
+        let mut filled = 0;
+
+        while filled < N {
+            let len = reader.varint()?;
+            ensure!(
+                len > 0 && len <= (N - filled) as u64,
+                "run of {} units overflows the chunk",
+                len
+            );
+
+            let unit = reader.unit()?;
+            data[filled..filled + len as usize]
+                .iter_mut()
+                .for_each(|u| *u = unit);
+            filled += len as usize;
+        }
+
+        ensure!(reader.is_empty(), "trailing bytes after chunk");
+        Ok(Self::from(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    type TestChunk = Chunk<Dim, u16, 64>;
+
+    /// Xorshift, enough to cover many chunk layouts reproducibly.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    fn random_chunk(rng: &mut Rng) -> TestChunk {
+        // Few distinct values and long runs, as well as noise
+        let palette = 1 + rng.next() % 300;
+        let mut data = [0; 64];
+        let mut i = 0;
+
+        while i < 64 {
+            let len = (1 + rng.next() % 20) as usize;
+            let unit = (rng.next() % palette) as u16;
+            data[i..64.min(i + len)].iter_mut().for_each(|u| *u = unit);
+            i += len;
+        }
+
+        Chunk::from(data)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..1000 {
+            let chunk = random_chunk(&mut rng);
+            assert_eq!(TestChunk::decode_rle(&chunk.encode_rle()).unwrap(), chunk);
+        }
+
+        let uniform = TestChunk::default();
+        assert_eq!(uniform.encode_rle().len(), 6);
+        assert_eq!(
+            TestChunk::decode_rle(&uniform.encode_rle()).unwrap(),
+            uniform
+        );
+    }
+
+    #[test]
+    fn rejects_truncated() {
+        let mut rng = Rng(7);
+        let bytes = random_chunk(&mut rng).encode_rle();
+
+        for len in 0..bytes.len() {
+            assert!(TestChunk::decode_rle(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let bytes = TestChunk::default().encode_rle();
+        let with = |i: usize, b: u8| {
+            let mut bytes = bytes.clone();
+            bytes[i] = b;
+            bytes
+        };
+
+        // Version, side, unit size
+        assert!(TestChunk::decode_rle(&with(0, RLE_VERSION + 1)).is_err());
+        assert!(TestChunk::decode_rle(&with(1, 8)).is_err());
+        assert!(TestChunk::decode_rle(&with(2, 1)).is_err());
+        // Empty run, run past the end of the chunk
+        assert!(TestChunk::decode_rle(&with(3, 0)).is_err());
+        assert!(TestChunk::decode_rle(&with(3, 65)).is_err());
+        // Trailing bytes
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(TestChunk::decode_rle(&trailing).is_err());
+        // Unterminated varint
+        assert!(TestChunk::decode_rle(&[
+            RLE_VERSION,
+            4,
+            2,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0x01
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn random_bytes_do_not_panic() {
+        let mut rng = Rng(42);
+
+        for _ in 0..10_000 {
+            let len = (rng.next() % 64) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+
+            // Keeps a valid header half of the time to reach the runs
+            if rng.next() % 2 == 0 {
+                bytes.splice(0..0, vec![RLE_VERSION, 4, 2]);
+            }
+
+            TestChunk::decode_rle(&bytes).ok();
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Serde implementations of chunk data.
+//!
+//! A `Chunk` is written as a fixed size tuple of its units,
+//! which binary formats encode without any length prefix.
+use super::{Accessor, Chunk, ChunkVec, Unit};
+use serde::{
+    de::{Error, Expected, SeqAccess, Visitor},
+    ser::SerializeTuple,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{fmt, marker::PhantomData};
+
+impl<A: Accessor, T: Unit + Serialize, const N: usize> Serialize for Chunk<A, T, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(N)?;
+        for unit in self.data.iter() {
+            tuple.serialize_element(unit)?;
+        }
+        tuple.end()
+    }
+}
+
+struct ChunkVisitor<A, T, const N: usize>(PhantomData<(A, T)>);
+
+impl<'de, A: Accessor, T: Unit + Deserialize<'de>, const N: usize> Visitor<'de>
+    for ChunkVisitor<A, T, N>
+{
+    type Value = Chunk<A, T, N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a chunk of {} units", N)
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {
+        let mut data = [T::default(); N];
+
+        for (i, unit) in data.iter_mut().enumerate() {
+            *unit = seq
+                .next_element()?
+                .ok_or_else(|| Error::invalid_length(i, &self))?;
+        }
+
+        if seq.next_element::<T>()?.is_some() {
+            return Err(Error::invalid_length(N + 1, &self));
+        }
+
+        Ok(Chunk::from(data))
+    }
+}
+
+impl<'de, A: Accessor, T: Unit + Deserialize<'de>, const N: usize> Deserialize<'de>
+    for Chunk<A, T, N>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple(N, ChunkVisitor(PhantomData))
+    }
+}
+
+impl<A: Accessor, T: Unit + Serialize> Serialize for ChunkVec<T, A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data.serialize(serializer)
+    }
+}
+
+/// Expected length of a `ChunkVec`, for errors.
+struct CubeLen(usize);
+
+impl Expected for CubeLen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a chunk of {} units", self.0)
+    }
+}
+
+impl<'de, A: Accessor, T: Unit + Deserialize<'de>> Deserialize<'de> for ChunkVec<T, A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = Vec::deserialize(deserializer)?;
+        if data.len() != A::CUBE_LEN {
+            return Err(Error::invalid_length(data.len(), &CubeLen(A::CUBE_LEN)));
+        }
+
+        Ok(Self {
+            data,
+            state: PhantomData::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 2;
+    }
+
+    type TestChunk = Chunk<Dim, u16, 8>;
+
+    fn chunk() -> TestChunk {
+        Chunk::from([0, 1, 2, 3, 300, 5, 6, u16::MAX])
+    }
+
+    #[test]
+    fn bincode_round_trip() {
+        let bytes = bincode::serialize(&chunk()).unwrap();
+        assert_eq!(bytes.len(), 8 * 2);
+        assert_eq!(bincode::deserialize::<TestChunk>(&bytes).unwrap(), chunk());
+
+        assert!(bincode::deserialize::<TestChunk>(&bytes[..15]).is_err());
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        let text = ron::to_string(&chunk()).unwrap();
+        assert_eq!(ron::from_str::<TestChunk>(&text).unwrap(), chunk());
+
+        assert!(ron::from_str::<TestChunk>("(0, 1, 2)").is_err());
+        assert!(ron::from_str::<TestChunk>("(0, 1, 2, 3, 4, 5, 6, 7, 8)").is_err());
+    }
+
+    #[test]
+    fn chunk_vec_round_trip() {
+        let vec = ChunkVec::from(chunk());
+        let bytes = bincode::serialize(&vec).unwrap();
+        let back: ChunkVec<u16, Dim> = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(back, vec);
+
+        let decode = |len: usize| {
+            let bytes = bincode::serialize(&vec![0u16; len]).unwrap();
+            bincode::deserialize::<ChunkVec<u16, Dim>>(&bytes)
+        };
+        assert!(decode(8).is_ok());
+        assert!(decode(9).is_err());
+
+        let error = decode(7).unwrap_err().to_string();
+        assert!(error.contains("a chunk of 8 units"), "{}", error);
+    }
+}
@@ -0,0 +1,98 @@
+use super::{
+    mesh::{neighbour, Face},
+    Accessor, Chunk, Unit,
+};
+
+/// Which faces of a chunk can see each other through its open voxels,
+/// stored as a symmetric 6x6 bit matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkConnectivity {
+    bits: u64,
+}
+
+impl Default for ChunkConnectivity {
+    /// Every face is connected, until the chunk is actually computed.
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl ChunkConnectivity {
+    const FULL: u64 = (1 << 36) - 1;
+
+    /// Every face connected to every other, e.g. an empty chunk.
+    pub fn all() -> Self {
+        Self { bits: Self::FULL }
+    }
+
+    /// No face connected, e.g. a solid chunk.
+    pub fn none() -> Self {
+        Self { bits: 0 }
+    }
+
+    #[inline(always)]
+    fn bit(a: Face, b: Face) -> u64 {
+        1 << (a as u64 * 6 + b as u64)
+    }
+
+    pub fn connect(&mut self, a: Face, b: Face) {
+        self.bits |= Self::bit(a, b) | Self::bit(b, a);
+    }
+
+    pub fn connects(&self, a: Face, b: Face) -> bool {
+        self.bits & Self::bit(a, b) != 0
+    }
+
+    /// Flood fills the open voxels of a chunk, voxels for which
+    /// `opaque` returns false, connecting every pair of faces
+    /// touched by the same open region.
+    pub fn compute<A, T, F, const N: usize>(chunk: &Chunk<A, T, N>, opaque: F) -> Self
+    where
+        A: Accessor,
+        T: Unit,
+        F: Fn(&T) -> bool,
+    {
+        let mut out = Self::none();
+        let mut visited = vec![false; N];
+        let mut stack = Vec::new();
+
+        for start in 0..N {
+            if visited[start] || opaque(&chunk.data[start]) {
+                continue;
+            }
+
+            let mut touched = 0u8;
+            visited[start] = true;
+            stack.push(start);
+
+            while let Some(i) = stack.pop() {
+                let pos = A::from_index(i);
+
+                for &face in Face::ALL.iter() {
+                    match neighbour::<A>(pos, face) {
+                        None => touched |= 1 << face as u8,
+                        Some(n) => {
+                            let n = A::to_index(n);
+                            if !visited[n] && !opaque(&chunk.data[n]) {
+                                visited[n] = true;
+                                stack.push(n);
+                            }
+                        }
+                    }
+                }
+            }
+
+            for &a in Face::ALL.iter().filter(|&&f| touched & (1 << f as u8) != 0) {
+                for &b in Face::ALL.iter().filter(|&&f| touched & (1 << f as u8) != 0) {
+                    out.connect(a, b);
+                }
+            }
+
+            if out.bits == Self::FULL {
+                break;
+            }
+        }
+
+        out
+    }
+}
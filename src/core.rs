@@ -73,8 +73,19 @@ pub mod input_event {
     };
 
     #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(
+        feature = "serialize",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(into = "InputRepr", from = "InputRepr")
+    )]
     #[non_exhaustive]
     /// Represents all possible inputs.
+    ///
+    /// With the `serialize` feature, serializing is one-way for the canvas
+    /// of `Key` and `Char` inputs: a `TypeId` isn't stable across builds,
+    /// so deserialized ones belong to [`DeserializedCanvas`] instead.
+    /// Every other input round-trips, e.g. replays should match keys
+    /// with [`key`](Input::key) rather than [`key_win`](Input::key_win).
     pub enum Input {
         /// A key
         Key { id: TypeId, keystate: KeyState },
@@ -90,6 +101,7 @@ pub mod input_event {
 
     /// A Key input with its corresponding state.
     #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
     pub enum KeyState {
         /// Key was pressed in a frame.
         Pressed(VirtualKeyCode),
@@ -120,6 +132,104 @@ pub mod input_event {
             None
         }
     }
+
+    /// The canvas of every deserialized `Key` and `Char` input.
+    #[cfg(feature = "serialize")]
+    #[derive(Debug)]
+    pub struct DeserializedCanvas;
+    #[cfg(feature = "serialize")]
+    impl CanvasTag for DeserializedCanvas {}
+
+    /// `Input` without its canvas id.
+    #[cfg(feature = "serialize")]
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+    enum InputRepr {
+        Key(KeyState),
+        Char(char),
+        CursorPosition(f64, f64),
+        MouseDelta(f64, f64),
+        MouseWheelDelta(f64, f64),
+    }
+
+    #[cfg(feature = "serialize")]
+    impl From<Input> for InputRepr {
+        fn from(input: Input) -> Self {
+            match input {
+                Input::Key { keystate, .. } => Self::Key(keystate),
+                Input::Char { character, .. } => Self::Char(character),
+                Input::CursorPosition(x, y) => Self::CursorPosition(x, y),
+                Input::MouseDelta(x, y) => Self::MouseDelta(x, y),
+                Input::MouseWheelDelta(x, y) => Self::MouseWheelDelta(x, y),
+            }
+        }
+    }
+
+    #[cfg(feature = "serialize")]
+    impl From<InputRepr> for Input {
+        fn from(repr: InputRepr) -> Self {
+            let id = TypeId::of::<DeserializedCanvas>();
+
+            match repr {
+                InputRepr::Key(keystate) => Input::Key { id, keystate },
+                InputRepr::Char(character) => Input::Char { id, character },
+                InputRepr::CursorPosition(x, y) => Input::CursorPosition(x, y),
+                InputRepr::MouseDelta(x, y) => Input::MouseDelta(x, y),
+                InputRepr::MouseWheelDelta(x, y) => Input::MouseWheelDelta(x, y),
+            }
+        }
+    }
+
+    #[cfg(all(test, feature = "serialize"))]
+    mod tests {
+        use super::*;
+
+        #[derive(Debug)]
+        struct Main;
+        impl CanvasTag for Main {}
+
+        fn round_trip(input: Input) -> Input {
+            let text = ron::to_string(&input).unwrap();
+            let back: Input = ron::from_str(&text).unwrap();
+
+            let bytes = bincode::serialize(&input).unwrap();
+            assert_eq!(bincode::deserialize::<Input>(&bytes).unwrap(), back);
+
+            back
+        }
+
+        #[test]
+        fn input_round_trip() {
+            let inputs = [
+                Input::CursorPosition(12., 34.5),
+                Input::MouseDelta(-1., 2.),
+                Input::MouseWheelDelta(0., -3.),
+            ];
+
+            for &input in inputs.iter() {
+                assert_eq!(round_trip(input), input);
+            }
+        }
+
+        #[test]
+        fn canvas_is_not_persisted() {
+            let id = TypeId::of::<Main>();
+            let keystate = KeyState::Pressed(VirtualKeyCode::Space);
+
+            let key = round_trip(Input::Key { id, keystate });
+            assert_eq!(key.key(), Some(keystate));
+            assert_eq!(key.key_win::<Main>(), None);
+            assert_eq!(key.key_win::<DeserializedCanvas>(), Some(keystate));
+
+            let character = round_trip(Input::Char { id, character: 'x' });
+            assert_eq!(
+                character,
+                Input::Char {
+                    id: TypeId::of::<DeserializedCanvas>(),
+                    character: 'x'
+                }
+            );
+        }
+    }
 }
 
 /// Events and some utility methods.
@@ -152,36 +262,3 @@ pub mod events {
         id
     }
 }
-
-#[cfg(feature = "extent")]
-/// This is fun ngl
-pub mod extensive {
-    use super::ecs::Schedule;
-    /// A State dictates which systems are run at some point in time;
-    /// most of the time, you don't want all of your systems to run, a state
-    /// allows you to to mutate your game's behavior
-    ///
-    /// If you want T to store some data,
-    /// then you must push an instance to `Resources`
-    /// and retrieve it from there
-    pub trait State {}
-
-    /// Manipulate the world and resources using a schedule
-    /// to initiate towards another state, it's recommended to
-    /// also perform cleanups here
-    pub trait TransitionTo<T: State>: State {
-        /// Returns a schedule that will only be executed once
-        fn transition() -> Schedule;
-    }
-
-    use std::marker::PhantomData;
-
-    use shrinkwraprs::*;
-    #[derive(Shrinkwrap)]
-    #[shrinkwrap(mutable)]
-    pub struct CurrentState<T: State> {
-        #[shrinkwrap(main_field)]
-        pub sched: Schedule,
-        state: PhantomData<T>,
-    }
-}
@@ -6,6 +6,7 @@ use legion::{storage::Component, *};
 
 /// A kind of 3D Projection
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub enum Project3D {
     Orthographic,
@@ -14,6 +15,7 @@ pub enum Project3D {
 
 /// A screen projection that produces a matrix.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Projection {
     projection: Project3D,
     fov_y: Rad<f32>,
@@ -87,3 +89,34 @@ pub fn camera_system<V: View + Component>() -> impl systems::Runnable {
             }
         })
 }
+
+#[cfg(all(test, feature = "serialize"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projection_round_trip() {
+        let res = Resolution::from([800, 600]);
+
+        for &projection in [Project3D::Perspective, Project3D::Orthographic].iter() {
+            let projection = Projection {
+                projection,
+                fov_y: Rad(1.2),
+                z_near: 0.1,
+                z_far: 100.,
+            };
+
+            let text = ron::to_string(&projection).unwrap();
+            let back: Projection = ron::from_str(&text).unwrap();
+            assert_eq!(back.matrix(&res), projection.matrix(&res));
+
+            let bytes = bincode::serialize(&projection).unwrap();
+            let back: Projection = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(back.matrix(&res), projection.matrix(&res));
+            assert_eq!(
+                (back.fov_y, back.z_near, back.z_far),
+                (projection.fov_y, projection.z_near, projection.z_far)
+            );
+        }
+    }
+}
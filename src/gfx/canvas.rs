@@ -1,4 +1,5 @@
 use crate::{
+    app::ResumeApp,
     core::{
         ecs::{
             systems::{Builder, Runnable},
@@ -83,6 +84,74 @@ pub enum UpdateKind {
     Quit,
 }
 
+/// A resource deciding what happens when a canvas is closed,
+/// a [`CloseRequested`](CloseRequested) event is written in any case.
+///
+/// Defaults to quitting on the `DefaultWindow`, or on any canvas
+/// without the `default-window` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClosePolicy {
+    /// Ends the app once any canvas is closed.
+    QuitOnAny,
+    /// Ends the app once the canvas of this tag is closed,
+    /// the others are hidden, see [`quit_on`](ClosePolicy::quit_on).
+    QuitOn(TypeId),
+    /// Only writes the event, canvases stay open and the app keeps running.
+    /// Read the `EventChannel<CloseRequested>` to handle it,
+    /// e.g. ask for confirmation before calling `ResumeApp::end`,
+    /// or hide the canvas with [`set_visible`](Canvas::set_visible).
+    Emit,
+}
+
+impl ClosePolicy {
+    pub fn quit_on<C: CanvasTag>() -> Self {
+        Self::QuitOn(TypeId::of::<C>())
+    }
+
+    /// What to do once the canvas of tag `id` is closed.
+    fn on_close(self, id: TypeId) -> CloseAction {
+        match self {
+            Self::QuitOnAny => CloseAction::Quit,
+            Self::QuitOn(main) if main == id => CloseAction::Quit,
+            Self::QuitOn(_) => CloseAction::Hide,
+            Self::Emit => CloseAction::Nothing,
+        }
+    }
+}
+
+/// Outcome of a `ClosePolicy` for a closed canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CloseAction {
+    Quit,
+    Hide,
+    Nothing,
+}
+
+impl Default for ClosePolicy {
+    #[cfg(feature = "default-window")]
+    fn default() -> Self {
+        Self::quit_on::<DefaultWindow>()
+    }
+
+    #[cfg(not(feature = "default-window"))]
+    fn default() -> Self {
+        Self::QuitOnAny
+    }
+}
+
+/// An event written when the user asks to close a canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CloseRequested {
+    id: TypeId,
+}
+
+impl CloseRequested {
+    /// Whether the closed canvas is tagged with `C`.
+    pub fn is<C: CanvasTag>(&self) -> bool {
+        self.id == TypeId::of::<C>()
+    }
+}
+
 /// A resource of the screen's perceived
 /// Frames per second.
 #[derive(Debug, Shrinkwrap)]
@@ -90,6 +159,11 @@ pub enum UpdateKind {
 pub struct FpsCounter(pub TpsCounter);
 
 impl<C: CanvasTag> Canvas<C> {
+    /// Shows or hides the window, a hidden canvas can be shown again.
+    pub fn set_visible(&self, visible: bool) {
+        self.window_handle.set_visible(visible);
+    }
+
     /// An `System` for updating Canvas through Window events.
     pub fn update_system(&self, id: ReaderId<CanvasUpdate>) -> impl Runnable {
         let mut reader_id = id;
@@ -98,7 +172,10 @@ impl<C: CanvasTag> Canvas<C> {
         SystemBuilder::new(sys_name)
             .write_resource::<Canvas<C>>()
             .read_resource::<EventChannel<CanvasUpdate>>()
-            .build(move |_, _, (canvas, updates), _| {
+            .read_resource::<ClosePolicy>()
+            .write_resource::<EventChannel<CloseRequested>>()
+            .write_resource::<ResumeApp>()
+            .build(move |_, _, (canvas, updates, policy, closed, app), _| {
                 updates
                     .read(&mut reader_id)
                     .filter_map(|u| u.is_win::<C>())
@@ -110,8 +187,14 @@ impl<C: CanvasTag> Canvas<C> {
                         }
 
                         UpdateKind::Quit => {
-                            // TODO: Create a configurable Scheduling
-                            // application architecture to cater this.
+                            let id = TypeId::of::<C>();
+                            closed.single_write(CloseRequested { id });
+
+                            match policy.on_close(id) {
+                                CloseAction::Quit => app.end(),
+                                CloseAction::Hide => canvas.set_visible(false),
+                                CloseAction::Nothing => {}
+                            }
                         }
                     })
             })
@@ -213,6 +296,11 @@ impl Default for WindowEventLoop {
 pub fn event_routine(_: &mut World, r: &mut Resources, b: &mut Builder) {
     b.add_thread_local(window_event_system(r));
 
+    insert_if_none(r, ClosePolicy::default());
+    if !r.contains::<EventChannel<CloseRequested>>() {
+        new_channel::<CloseRequested>(r);
+    }
+
     #[cfg(feature = "default-window")]
     {
         let main_window: Canvas<DefaultWindow> = {
@@ -270,13 +358,15 @@ fn window_event_system(r: &mut Resources) -> impl Runnable {
                         } => {
                             if let Some(&id) = map.get(&window_id) {
                                 match event {
-                                    WindowEvent::CloseRequested | WindowEvent::Destroyed => {
-                                        map.remove(&window_id);
-
+                                    WindowEvent::CloseRequested => {
                                         let update = UpdateKind::Quit;
                                         canvas_channel.single_write(CanvasUpdate { id, update });
                                     }
 
+                                    WindowEvent::Destroyed => {
+                                        map.remove(&window_id);
+                                    }
+
                                     WindowEvent::KeyboardInput {
                                         input:
                                             KeyboardInput {
@@ -349,3 +439,32 @@ fn window_event_system(r: &mut Resources) -> impl Runnable {
             },
         )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Main;
+    impl CanvasTag for Main {}
+
+    #[derive(Debug)]
+    struct Inventory;
+    impl CanvasTag for Inventory {}
+
+    #[test]
+    fn close_policy() {
+        let main = TypeId::of::<Main>();
+        let inventory = TypeId::of::<Inventory>();
+
+        let policy = ClosePolicy::quit_on::<Main>();
+        assert_eq!(policy.on_close(main), CloseAction::Quit);
+        assert_eq!(policy.on_close(inventory), CloseAction::Hide);
+
+        assert_eq!(
+            ClosePolicy::QuitOnAny.on_close(inventory),
+            CloseAction::Quit
+        );
+        assert_eq!(ClosePolicy::Emit.on_close(main), CloseAction::Nothing);
+    }
+}
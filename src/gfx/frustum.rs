@@ -0,0 +1,334 @@
+use super::camera::Camera;
+#[cfg(feature = "gui")]
+use super::InstanceRaw;
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, Transform, Vector3, Vector4};
+use legion::{storage::Component, *};
+use shrinkwraprs::*;
+
+/// A plane where `normal.dot(p) + d == 0`,
+/// points in front of the plane have a positive distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub d: f32,
+}
+
+impl Plane {
+    /// Builds a normalized plane from `ax + by + cz + d`,
+    /// a degenerate plane, e.g. the far plane of an infinite projection,
+    /// has a zero normal so that every point is in front of it.
+    fn from_row(row: Vector4<f32>) -> Self {
+        let normal = row.truncate();
+        let len = normal.magnitude();
+
+        if len.is_nan() || len <= f32::EPSILON {
+            return Self {
+                normal: Vector3::new(0., 0., 0.),
+                d: 0.,
+            };
+        }
+
+        Self {
+            normal: normal / len,
+            d: row.w / len,
+        }
+    }
+
+    /// Signed distance between a point and the plane.
+    pub fn distance(&self, p: Point3<f32>) -> f32 {
+        self.normal.dot(Vector3::new(p.x, p.y, p.z)) + self.d
+    }
+}
+
+/// An axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// The box of a cube with `side` length starting at `min`.
+    pub fn cube(min: Point3<f32>, side: f32) -> Self {
+        Self {
+            min,
+            max: min + Vector3::new(side, side, side),
+        }
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        Point3::new(
+            (self.min.x + self.max.x) / 2.,
+            (self.min.y + self.max.y) / 2.,
+            (self.min.z + self.max.z) / 2.,
+        )
+    }
+
+    /// The box enclosing this box once transformed by `m`,
+    /// e.g. the world bounds of an instance from its model matrix.
+    pub fn transformed(&self, m: &Matrix4<f32>) -> Self {
+        let center = m.transform_point(self.center());
+        let half = (self.max - self.min) / 2.;
+
+        let extent = |row: usize| {
+            m.x[row].abs() * half.x + m.y[row].abs() * half.y + m.z[row].abs() * half.z
+        };
+        let extent = Vector3::new(extent(0), extent(1), extent(2));
+
+        Self {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+}
+
+/// The six planes enclosing everything a camera sees, pointing inwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far planes.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a projection * view matrix,
+    /// works for both perspective and orthographic projections
+    /// with cgmath's `[-1, 1]` clip space depth.
+    pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+
+        Self {
+            planes: [
+                Plane::from_row(r3 + r0),
+                Plane::from_row(r3 - r0),
+                Plane::from_row(r3 + r1),
+                Plane::from_row(r3 - r1),
+                Plane::from_row(r3 + r2),
+                Plane::from_row(r3 - r2),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, p: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.distance(p) >= 0.)
+    }
+
+    /// Conservative test, may accept spheres just outside of a corner.
+    pub fn intersects_sphere(&self, center: Point3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance(center) >= -radius)
+    }
+
+    /// Conservative test, may accept boxes just outside of a corner.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal
+            let pick = |n: f32, min: f32, max: f32| if n >= 0. { max } else { min };
+            let p = Point3::new(
+                pick(plane.normal.x, aabb.min.x, aabb.max.x),
+                pick(plane.normal.y, aabb.min.y, aabb.max.y),
+                pick(plane.normal.z, aabb.min.z, aabb.max.z),
+            );
+
+            plane.distance(p) >= 0.
+        })
+    }
+}
+
+impl From<&Camera> for Frustum {
+    fn from(cam: &Camera) -> Self {
+        Self::from_matrix(&cam.0)
+    }
+}
+
+/// World space bounds of an entity used for culling.
+#[derive(Debug, Clone, Copy, PartialEq, Shrinkwrap)]
+#[shrinkwrap(mutable)]
+pub struct Bounds(pub Aabb);
+
+/// Whether an entity was inside the view frustum this frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Shrinkwrap)]
+#[shrinkwrap(mutable)]
+pub struct Visible(pub bool);
+
+impl Default for Visible {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// Culling system, updates the `Visible` component of every entity
+/// with `Bounds` against the frustum of the camera tagged with `M`.
+pub fn culling_system<M: Component>() -> impl systems::Runnable {
+    SystemBuilder::new("FrustumCullingSystem")
+        .with_query(<&Camera>::query().filter(component::<M>()))
+        .with_query(<(&Bounds, &mut Visible)>::query())
+        .build(|_, world, _, (cameras, bounded)| {
+            let frustum = match cameras.iter(world).next() {
+                Some(cam) => Frustum::from(cam),
+                None => return,
+            };
+
+            for (bounds, visible) in bounded.iter_mut(world) {
+                **visible = frustum.intersects_aabb(bounds);
+            }
+        })
+}
+
+/// Instances of a mesh, the visible ones being
+/// kept by the [`instance_culling_system`](instance_culling_system).
+#[cfg(feature = "gui")]
+#[doc(cfg(feature = "gui"))]
+#[derive(Debug, Clone)]
+pub struct Instances {
+    /// Bounds of the instanced mesh, in model space.
+    pub mesh_bounds: Aabb,
+    pub instances: Vec<InstanceRaw>,
+    visible: Vec<InstanceRaw>,
+}
+
+#[cfg(feature = "gui")]
+impl Instances {
+    pub fn new(mesh_bounds: Aabb, instances: Vec<InstanceRaw>) -> Self {
+        Self {
+            mesh_bounds,
+            visible: instances.clone(),
+            instances,
+        }
+    }
+
+    /// Keeps the instances whose bounds intersect `frustum`.
+    pub fn cull(&mut self, frustum: &Frustum) {
+        let bounds = self.mesh_bounds;
+
+        self.visible.clear();
+        self.visible.extend(
+            self.instances
+                .iter()
+                .filter(|i| frustum.intersects_aabb(&bounds.transformed(&i.model()))),
+        );
+    }
+
+    /// The instances to draw, as of the last culling.
+    pub fn visible(&self) -> &[InstanceRaw] {
+        &self.visible
+    }
+}
+
+/// Culling system, keeps the visible `Instances` of every entity
+/// against the frustum of the camera tagged with `M`.
+#[cfg(feature = "gui")]
+#[doc(cfg(feature = "gui"))]
+pub fn instance_culling_system<M: Component>() -> impl systems::Runnable {
+    SystemBuilder::new("InstanceCullingSystem")
+        .with_query(<&Camera>::query().filter(component::<M>()))
+        .with_query(<&mut Instances>::query())
+        .build(|_, world, _, (cameras, instanced)| {
+            let frustum = match cameras.iter(world).next() {
+                Some(cam) => Frustum::from(cam),
+                None => return,
+            };
+
+            for instances in instanced.iter_mut(world) {
+                instances.cull(&frustum);
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{ortho, perspective, Deg, SquareMatrix};
+
+    fn perspective_camera() -> Frustum {
+        let view = Matrix4::look_at_rh(
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., -1.),
+            Vector3::unit_y(),
+        );
+
+        Frustum::from_matrix(&(perspective(Deg(90.), 1., 0.1, 100.) * view))
+    }
+
+    fn cube(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb::cube(Point3::new(x - 0.5, y - 0.5, z - 0.5), 1.)
+    }
+
+    #[test]
+    fn planes_are_normalized() {
+        for plane in perspective_camera().planes.iter() {
+            assert!((plane.normal.magnitude() - 1.).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn perspective_aabb() {
+        let frustum = perspective_camera();
+
+        // Inside
+        assert!(frustum.intersects_aabb(&cube(0., 0., -10.)));
+        assert!(frustum.contains_point(Point3::new(0., 0., -10.)));
+        // Outside, to the side and past the far plane
+        assert!(!frustum.intersects_aabb(&cube(50., 0., -10.)));
+        assert!(!frustum.intersects_aabb(&cube(0., 0., -200.)));
+        // Straddling the right plane and the near plane
+        assert!(frustum.intersects_aabb(&cube(10., 0., -10.)));
+        assert!(frustum.intersects_aabb(&cube(0., 0., 0.)));
+        // Behind the camera
+        assert!(!frustum.intersects_aabb(&cube(0., 0., 10.)));
+        assert!(!frustum.contains_point(Point3::new(0., 0., 10.)));
+    }
+
+    #[test]
+    fn orthographic_aabb() {
+        let view = Matrix4::look_at_rh(
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., -1.),
+            Vector3::unit_y(),
+        );
+        let frustum = Frustum::from_matrix(&(ortho(-10., 10., -10., 10., 0.1, 100.) * view));
+
+        assert!(frustum.intersects_aabb(&cube(9., 9., -50.)));
+        assert!(frustum.intersects_aabb(&cube(10., 0., -50.)));
+        assert!(!frustum.intersects_aabb(&cube(12., 0., -50.)));
+        assert!(!frustum.intersects_aabb(&cube(0., 0., 5.)));
+    }
+
+    #[test]
+    fn spheres() {
+        let frustum = perspective_camera();
+
+        assert!(frustum.intersects_sphere(Point3::new(0., 0., -10.), 1.));
+        assert!(frustum.intersects_sphere(Point3::new(0., 0., 0.5), 1.));
+        assert!(!frustum.intersects_sphere(Point3::new(0., 0., 5.), 1.));
+    }
+
+    #[test]
+    fn degenerate_planes_are_not_nan() {
+        let frustum = Frustum::from_matrix(&Matrix4::from_scale(0.));
+
+        for plane in frustum.planes.iter() {
+            assert!(!plane.normal.x.is_nan() && !plane.d.is_nan());
+        }
+        assert!(frustum.contains_point(Point3::new(1., 2., 3.)));
+
+        let frustum = Frustum::from_matrix(&Matrix4::identity());
+        assert!(frustum.planes.iter().all(|p| !p.d.is_nan()));
+    }
+
+    #[test]
+    fn transformed_aabb() {
+        let unit = cube(0., 0., 0.);
+        let m =
+            Matrix4::from_translation(Vector3::new(0., 0., -10.)) * Matrix4::from_angle_y(Deg(45.));
+        let moved = unit.transformed(&m);
+
+        assert!((moved.center() - Point3::new(0., 0., -10.)).magnitude() < 1e-5);
+        assert!((moved.max.x - moved.min.x - 2f32.sqrt()).abs() < 1e-5);
+        assert!(perspective_camera().intersects_aabb(&moved));
+    }
+}
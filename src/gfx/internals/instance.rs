@@ -1,4 +1,5 @@
 use super::vertex::Vertex;
+use crate::transform::{GlobalTransform, Transform};
 use bytemuck::{Pod, Zeroable};
 use cgmath::{Matrix4, Quaternion, Vector3};
 use wgpu::{
@@ -21,6 +22,20 @@ impl From<Instance> for InstanceRaw {
     }
 }
 
+impl From<Instance> for Transform {
+    fn from(instance: Instance) -> Self {
+        Transform::from_translation(instance.position).with_rotation(instance.rotation)
+    }
+}
+
+impl From<&GlobalTransform> for InstanceRaw {
+    fn from(global: &GlobalTransform) -> Self {
+        Self {
+            model: global.0.into(),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct InstanceRaw {
@@ -59,5 +74,11 @@ impl Vertex for InstanceRaw {
     }
 }
 
+impl InstanceRaw {
+    pub fn model(&self) -> Matrix4<f32> {
+        self.model.into()
+    }
+}
+
 unsafe impl Pod for InstanceRaw {}
 unsafe impl Zeroable for InstanceRaw {}
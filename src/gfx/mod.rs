@@ -1,15 +1,28 @@
+#[cfg(feature = "gui")]
 mod internals;
-pub use internals::texture::Texture;
+#[cfg(feature = "gui")]
+pub use internals::{
+    instance::{Instance, InstanceRaw},
+    texture::Texture,
+};
 
 pub mod camera;
+#[cfg(feature = "gui")]
+#[doc(cfg(feature = "gui"))]
 pub mod canvas;
+pub mod frustum;
+#[cfg(feature = "gui")]
+#[doc(cfg(feature = "gui"))]
 pub mod paint_brush;
 
+#[cfg(feature = "gui")]
 pub use wgpu::BackendBit;
+#[cfg(feature = "gui")]
 pub use winit::{dpi::PhysicalSize, window::Window};
 
 use shrinkwraprs::*;
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 /// Represents 2D screen dimensions and its aspect ratio.
 pub struct Resolution {
     xy: (u32, u32),
@@ -30,6 +43,7 @@ impl Resolution {
     }
 }
 
+#[cfg(feature = "gui")]
 impl From<PhysicalSize<u32>> for Resolution {
     fn from(size: PhysicalSize<u32>) -> Self {
         Self {
@@ -62,3 +76,25 @@ impl From<(u32, u32)> for Resolution {
 /// Limit framerate to device specification.
 // TODO: don't forget this
 pub struct Vsync(pub bool);
+
+#[cfg(all(test, feature = "serialize"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution_round_trip() {
+        let res = Resolution::from([1920, 1080]);
+
+        let text = ron::to_string(&res).unwrap();
+        let bytes = bincode::serialize(&res).unwrap();
+        let decoded = [
+            ron::from_str::<Resolution>(&text).unwrap(),
+            bincode::deserialize::<Resolution>(&bytes).unwrap(),
+        ];
+
+        for back in decoded.iter() {
+            assert_eq!(back.dimensions(), res.dimensions());
+            assert_eq!(back.aspect(), res.aspect());
+        }
+    }
+}
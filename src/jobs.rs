@@ -0,0 +1,507 @@
+//! Background jobs, such as chunk generation, meshing or IO,
+//! running on their own thread pool beside the one executing systems.
+use crate::core::{
+    ecs::{
+        systems::{Builder, Runnable},
+        *,
+    },
+    events::{new_channel, EventChannel},
+};
+use anyhow::Result;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use shrinkwraprs::*;
+use std::{
+    any::{type_name, Any},
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    error::Error,
+    fmt,
+    hash::Hash,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering},
+        Arc, Mutex, PoisonError,
+    },
+};
+
+/// Default no. of jobs of a single `Jobs` queue running at once.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+
+/// A resource holding the thread pool shared by every `Jobs` queue.
+#[derive(Debug, Clone, Shrinkwrap)]
+pub struct JobPool(pub Arc<ThreadPool>);
+
+impl JobPool {
+    /// A pool of `num_threads` threads, `0` picking the no. of CPUs.
+    pub fn new(num_threads: usize) -> Result<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("voxl-job-{}", i))
+            .build()?;
+
+        Ok(Self(Arc::new(pool)))
+    }
+}
+
+impl Default for JobPool {
+    fn default() -> Self {
+        Self::new(0).expect("failed to build the job thread pool")
+    }
+}
+
+/// Handed to every job, long jobs should return early once cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(AtomicOrdering::Relaxed)
+    }
+
+    fn cancel(&self) {
+        self.0.store(true, AtomicOrdering::Relaxed);
+    }
+}
+
+/// The message of a job that panicked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobPanic(pub String);
+
+impl JobPanic {
+    fn from_payload(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "unknown panic".to_owned(),
+            },
+        };
+
+        Self(message)
+    }
+}
+
+impl fmt::Display for JobPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "job panicked: {}", self.0)
+    }
+}
+
+impl Error for JobPanic {}
+
+/// Outcome of a job, `Err` if it panicked.
+pub type JobResult<R> = std::result::Result<R, JobPanic>;
+
+type JobFn<R> = Box<dyn FnOnce(&Cancel) -> R + Send>;
+/// Results of the finished jobs, with the sequence no. they were spawned with.
+type Done<K, R> = Arc<Mutex<Vec<(K, u64, JobResult<R>)>>>;
+
+struct Pending<R> {
+    seq: u64,
+    // Only needed to make `Jobs` `Sync` without requiring `Sync` jobs,
+    // the job is never shared, only moved out once it starts.
+    job: Mutex<JobFn<R>>,
+}
+
+/// Entry of the pending heap, outdated once its job was replaced or cancelled.
+#[derive(Debug)]
+struct Queued<K> {
+    priority: Reverse<u32>,
+    seq: Reverse<u64>,
+    key: K,
+}
+
+impl<K> PartialEq for Queued<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+}
+
+impl<K> Eq for Queued<K> {}
+
+impl<K> Ord for Queued<K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.seq).cmp(&(other.priority, other.seq))
+    }
+}
+
+impl<K> PartialOrd for Queued<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A resource queuing jobs identified by a key, e.g. a `ChunkCoord`.
+///
+/// Jobs with the lowest priority value start first, at most `max_in_flight`
+/// at a time, and their results are collected with [`poll`](Jobs::poll).
+/// A key has at most one job, spawning another one replaces it.
+pub struct Jobs<K, R> {
+    pool: Arc<ThreadPool>,
+    max_in_flight: usize,
+    seq: u64,
+    heap: BinaryHeap<Queued<K>>,
+    pending: HashMap<K, Pending<R>>,
+    running: HashMap<K, (u64, Cancel)>,
+    in_flight: Arc<AtomicUsize>,
+    done: Done<K, R>,
+}
+
+impl<K, R> Jobs<K, R>
+where
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    R: Send + 'static,
+{
+    pub fn new(pool: &JobPool, max_in_flight: usize) -> Self {
+        Self {
+            pool: pool.0.clone(),
+            max_in_flight: max_in_flight.max(1),
+            seq: 0,
+            heap: BinaryHeap::new(),
+            pending: HashMap::new(),
+            running: HashMap::new(),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            done: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Queues a job, cancelling the previous job of the same key.
+    pub fn spawn<F>(&mut self, key: K, priority: u32, job: F)
+    where
+        F: FnOnce(&Cancel) -> R + Send + 'static,
+    {
+        self.cancel(&key);
+
+        self.seq += 1;
+        self.heap.push(Queued {
+            priority: Reverse(priority),
+            seq: Reverse(self.seq),
+            key: key.clone(),
+        });
+        self.pending.insert(
+            key,
+            Pending {
+                seq: self.seq,
+                job: Mutex::new(Box::new(job)),
+            },
+        );
+    }
+
+    /// Cancels the job of a key, whether it's pending or running.
+    /// The result of a running job is discarded.
+    pub fn cancel(&mut self, key: &K) -> bool {
+        if let Some((_, cancel)) = self.running.remove(key) {
+            cancel.cancel();
+            return true;
+        }
+
+        if self.pending.remove(key).is_none() {
+            return false;
+        }
+
+        self.compact();
+        true
+    }
+
+    /// Drops the heap entries of cancelled and replaced jobs
+    /// once they outnumber the pending ones.
+    fn compact(&mut self) {
+        if self.heap.len() <= 2 * self.pending.len() {
+            return;
+        }
+
+        let pending = &self.pending;
+        self.heap = self
+            .heap
+            .drain()
+            .filter(|q| pending.get(&q.key).map(|p| p.seq) == Some(q.seq.0))
+            .collect();
+    }
+
+    /// Cancels the jobs of every key for which `f` returns false,
+    /// e.g. chunks that left the load radius.
+    pub fn retain<F: FnMut(&K) -> bool>(&mut self, mut f: F) {
+        let cancelled: Vec<K> = self
+            .pending
+            .keys()
+            .chain(self.running.keys())
+            .filter(|k| !f(k))
+            .cloned()
+            .collect();
+
+        for key in cancelled.iter() {
+            self.cancel(key);
+        }
+    }
+
+    /// Updates the priority of every pending job,
+    /// e.g. after the player moved.
+    pub fn reprioritize<F: FnMut(&K) -> u32>(&mut self, mut f: F) {
+        self.heap.clear();
+
+        for (key, pending) in self.pending.iter() {
+            self.heap.push(Queued {
+                priority: Reverse(f(key)),
+                seq: Reverse(pending.seq),
+                key: key.clone(),
+            });
+        }
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.pending.contains_key(key) || self.running.contains_key(key)
+    }
+
+    /// No. of jobs waiting to start.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// No. of jobs started whose result wasn't polled yet.
+    pub fn running_len(&self) -> usize {
+        self.running.len()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty() && self.running.is_empty()
+    }
+
+    /// Starts pending jobs until `max_in_flight` of them are running,
+    /// cancelled jobs that didn't return yet included.
+    pub fn dispatch(&mut self) {
+        while self.in_flight.load(AtomicOrdering::Acquire) < self.max_in_flight {
+            let queued = match self.heap.pop() {
+                Some(queued) => queued,
+                None => break,
+            };
+
+            let key = queued.key;
+            if self.pending.get(&key).map(|p| p.seq) != Some(queued.seq.0) {
+                continue;
+            }
+
+            let Pending { seq, job } = self.pending.remove(&key).unwrap();
+            let job = job.into_inner().unwrap_or_else(PoisonError::into_inner);
+            let cancel = Cancel::default();
+            self.running.insert(key.clone(), (seq, cancel.clone()));
+
+            let in_flight = self.in_flight.clone();
+            let done = self.done.clone();
+            in_flight.fetch_add(1, AtomicOrdering::AcqRel);
+
+            self.pool.spawn(move || {
+                if !cancel.is_cancelled() {
+                    // A panic would otherwise abort the pool and leak `in_flight`
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| job(&cancel)))
+                        .map_err(JobPanic::from_payload);
+
+                    if !cancel.is_cancelled() {
+                        done.lock().unwrap().push((key, seq, result));
+                    }
+                }
+
+                in_flight.fetch_sub(1, AtomicOrdering::AcqRel);
+            });
+        }
+    }
+
+    /// Returns the results of the jobs finished since the last call,
+    /// then starts pending jobs.
+    pub fn poll(&mut self) -> Vec<(K, JobResult<R>)> {
+        let done = std::mem::take(&mut *self.done.lock().unwrap());
+        let mut out = Vec::with_capacity(done.len());
+
+        for (key, seq, result) in done {
+            // A job replaced or cancelled after it finished.
+            if self.running.get(&key).map(|&(s, _)| s) != Some(seq) {
+                continue;
+            }
+
+            self.running.remove(&key);
+            out.push((key, result));
+        }
+
+        self.dispatch();
+        out
+    }
+}
+
+impl<K, R> Drop for Jobs<K, R> {
+    fn drop(&mut self) {
+        for (_, cancel) in self.running.values() {
+            cancel.cancel();
+        }
+    }
+}
+
+/// An event holding the result of a finished job, `Err` if it panicked.
+#[derive(Debug, Clone)]
+pub struct JobDone<K, R> {
+    pub key: K,
+    pub result: JobResult<R>,
+}
+
+/// Returns a `System` polling `Jobs<K, R>` every frame,
+/// writing their results to an `EventChannel<JobDone<K, R>>`.
+pub fn job_system<K, R>() -> impl Runnable
+where
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    R: Send + Sync + 'static,
+{
+    SystemBuilder::new(format!(
+        "Jobs<{}, {}>System",
+        type_name::<K>(),
+        type_name::<R>()
+    ))
+    .write_resource::<Jobs<K, R>>()
+    .write_resource::<EventChannel<JobDone<K, R>>>()
+    .build(|_, _, (jobs, channel), _| {
+        for (key, result) in jobs.poll() {
+            channel.single_write(JobDone { key, result });
+        }
+    })
+}
+
+/// Inserts a `JobPool` if there's none, a `Jobs<K, R>` queue
+/// and its `JobDone` channel, then adds the [`job_system`](job_system).
+pub fn job_routine<K, R>(_: &mut World, r: &mut Resources, b: &mut Builder)
+where
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    R: Send + Sync + 'static,
+{
+    insert_if_none(r, JobPool::default());
+
+    let jobs = Jobs::<K, R>::new(&get_expect::<JobPool>(r), DEFAULT_MAX_IN_FLIGHT);
+    insert_if_none(r, jobs);
+
+    if !r.contains::<EventChannel<JobDone<K, R>>>() {
+        new_channel::<JobDone<K, R>>(r);
+    }
+
+    b.add_system(job_system::<K, R>());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    fn jobs<R: Send + 'static>() -> Jobs<char, R> {
+        Jobs::new(&JobPool::new(1).unwrap(), 1)
+    }
+
+    /// Polls until every job returned.
+    fn run<R: Send + 'static>(jobs: &mut Jobs<char, R>) -> Vec<(char, JobResult<R>)> {
+        let mut out = Vec::new();
+
+        while !jobs.is_idle() {
+            out.extend(jobs.poll());
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        out
+    }
+
+    fn keys<R>(results: &[(char, JobResult<R>)]) -> Vec<char> {
+        results.iter().map(|(k, _)| *k).collect()
+    }
+
+    #[test]
+    fn priority_order() {
+        let mut jobs = jobs();
+
+        for (key, priority) in [('a', 5), ('b', 1), ('c', 3), ('d', 1)].iter().copied() {
+            jobs.spawn(key, priority, move |_| key);
+        }
+
+        let results = run(&mut jobs);
+        assert_eq!(keys(&results), vec!['b', 'd', 'c', 'a']);
+        assert!(results.iter().all(|(k, r)| r.as_ref() == Ok(k)));
+    }
+
+    #[test]
+    fn reprioritize() {
+        let mut jobs = jobs();
+
+        for key in ['a', 'b', 'c'].iter().copied() {
+            jobs.spawn(key, 0, move |_| ());
+        }
+        jobs.reprioritize(|&k| 'z' as u32 - k as u32);
+
+        assert_eq!(keys(&run(&mut jobs)), vec!['c', 'b', 'a']);
+    }
+
+    #[test]
+    fn cancel_pending() {
+        let mut jobs = jobs();
+
+        for key in ['a', 'b', 'c', 'd'].iter().copied() {
+            jobs.spawn(key, 0, move |_| key);
+        }
+        // Replaced, only the second job of `a` runs
+        jobs.spawn('a', 0, |_| 'A');
+
+        assert!(jobs.cancel(&'b'));
+        assert!(!jobs.cancel(&'b'));
+        jobs.retain(|&k| k != 'c');
+        assert!(!jobs.contains(&'c'));
+
+        let results = run(&mut jobs);
+        assert_eq!(keys(&results), vec!['d', 'a']);
+        assert_eq!(results[1].1, Ok('A'));
+
+        // Cancelled entries don't pile up in the heap
+        for key in ['a', 'b', 'c'].iter().copied() {
+            jobs.spawn(key, 0, |_| ' ');
+        }
+        jobs.retain(|_| false);
+        assert!(jobs.is_idle());
+        assert!(jobs.heap.is_empty());
+    }
+
+    #[test]
+    fn cancel_running() {
+        let mut jobs = jobs();
+
+        jobs.spawn('a', 0, |cancel: &Cancel| {
+            while !cancel.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            'a'
+        });
+        jobs.spawn('b', 1, |_| 'b');
+
+        assert!(jobs.poll().is_empty());
+        assert_eq!(jobs.running_len(), 1);
+        assert!(jobs.cancel(&'a'));
+
+        // `b` only starts once `a` returned
+        assert_eq!(keys(&run(&mut jobs)), vec!['b']);
+    }
+
+    #[test]
+    fn non_sync_job() {
+        fn resource<T: Send + Sync>(_: &T) {}
+
+        let mut jobs = jobs();
+        resource(&jobs);
+
+        // `Cell` is `Send` but not `Sync`
+        let cell = std::cell::Cell::new(1);
+        jobs.spawn('a', 0, move |_| cell.get() + 1);
+        assert_eq!(run(&mut jobs), vec![('a', Ok(2))]);
+    }
+
+    #[test]
+    fn panicking_job() {
+        let mut jobs = jobs();
+
+        jobs.spawn('a', 0, |_| panic!("boom"));
+        jobs.spawn('b', 1, |_| 1);
+
+        let results = run(&mut jobs);
+        assert_eq!(results[0], ('a', Err(JobPanic("boom".to_owned()))));
+        assert_eq!(results[1], ('b', Ok(1)));
+        assert_eq!(jobs.in_flight.load(AtomicOrdering::Acquire), 0);
+    }
+}
@@ -2,9 +2,24 @@
 #![feature(option_expect_none)]
 
 pub mod app;
+pub mod assets;
+pub mod block;
+pub mod chunk;
 pub mod core;
 pub mod gfx;
+pub mod jobs;
+pub mod plugin;
+#[cfg(feature = "serialize")]
+#[doc(cfg(feature = "serialize"))]
+pub mod prefab;
+#[cfg(feature = "serialize")]
+#[doc(cfg(feature = "serialize"))]
+pub mod scene;
+pub mod stage;
+pub mod state;
 pub mod time;
+pub mod transform;
+pub mod world;
 
 pub mod math {
     pub mod ng {
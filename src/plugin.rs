@@ -0,0 +1,206 @@
+//! Plugins, routines declaring what they need to be set up after.
+use crate::{
+    app::{AppBuilder, Routine},
+    stage::Stage,
+};
+use anyhow::{bail, Result};
+use std::{any::type_name, collections::HashMap, marker::PhantomData};
+
+/// A piece of an application, e.g. rendering or chunk generation,
+/// set up once its dependencies are.
+pub trait Plugin: 'static {
+    /// Identifies the plugin, only the first plugin of a name is kept.
+    fn name(&self) -> &'static str {
+        type_name::<Self>()
+    }
+
+    /// Names of the plugins set up before this one.
+    fn dependencies(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Names of the plugins that can't be used along this one.
+    fn conflicts(&self) -> &[&'static str] {
+        &[]
+    }
+
+    fn build(&mut self, app: &mut AppBuilder);
+}
+
+/// A plugin setting up a `Routine`, named after it.
+pub struct RoutinePlugin<T: Routine> {
+    dependencies: Vec<&'static str>,
+    _m: PhantomData<T>,
+}
+
+impl<T: Routine> RoutinePlugin<T> {
+    pub fn new(dependencies: Vec<&'static str>) -> Self {
+        Self {
+            dependencies,
+            _m: PhantomData::default(),
+        }
+    }
+}
+
+impl<T: Routine> Default for RoutinePlugin<T> {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<T: Routine + 'static> Plugin for RoutinePlugin<T> {
+    fn name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn dependencies(&self) -> &[&'static str] {
+        &self.dependencies
+    }
+
+    fn build(&mut self, app: &mut AppBuilder) {
+        app.with_stage(Stage::Update, T::setup);
+    }
+}
+
+/// Checks the plugins' dependencies and conflicts,
+/// then returns the order of their indices to set them up in.
+///
+/// Plugins without dependencies between them keep their order of registration.
+pub(crate) fn plugin_order(plugins: &[Box<dyn Plugin>]) -> Result<Vec<usize>> {
+    let index: HashMap<&'static str, usize> = plugins
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.name(), i))
+        .collect();
+
+    let mut errors = Vec::new();
+    for p in plugins.iter() {
+        for dep in p.dependencies() {
+            if !index.contains_key(dep) {
+                errors.push(format!("`{}` depends on missing `{}`", p.name(), dep));
+            }
+        }
+
+        for conflict in p.conflicts() {
+            if index.contains_key(conflict) {
+                errors.push(format!("`{}` conflicts with `{}`", p.name(), conflict));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!("invalid plugins:\n{}", errors.join("\n"));
+    }
+
+    let mut remaining: Vec<usize> = plugins.iter().map(|p| p.dependencies().len()).collect();
+    let mut dependents = vec![Vec::new(); plugins.len()];
+    for (i, p) in plugins.iter().enumerate() {
+        for dep in p.dependencies() {
+            dependents[index[dep]].push(i);
+        }
+    }
+
+    let mut order = Vec::with_capacity(plugins.len());
+    let mut done = vec![false; plugins.len()];
+    while let Some(i) = (0..plugins.len()).find(|&i| !done[i] && remaining[i] == 0) {
+        done[i] = true;
+        order.push(i);
+
+        for &d in dependents[i].iter() {
+            remaining[d] -= 1;
+        }
+    }
+
+    if order.len() < plugins.len() {
+        let cycle: Vec<&str> = (0..plugins.len())
+            .filter(|&i| !done[i])
+            .map(|i| plugins[i].name())
+            .collect();
+
+        bail!(
+            "cyclic plugin dependencies between `{}`",
+            cycle.join("`, `")
+        );
+    }
+
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Named {
+        name: &'static str,
+        dependencies: Vec<&'static str>,
+        conflicts: Vec<&'static str>,
+    }
+
+    impl Plugin for Named {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn dependencies(&self) -> &[&'static str] {
+            &self.dependencies
+        }
+
+        fn conflicts(&self) -> &[&'static str] {
+            &self.conflicts
+        }
+
+        fn build(&mut self, _: &mut AppBuilder) {}
+    }
+
+    fn plugin(name: &'static str, dependencies: &[&'static str]) -> Box<dyn Plugin> {
+        Box::new(Named {
+            name,
+            dependencies: dependencies.to_vec(),
+            conflicts: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn dependencies_first() {
+        let plugins = vec![
+            plugin("render", &["window", "assets"]),
+            plugin("window", &[]),
+            plugin("ui", &["render"]),
+            plugin("assets", &[]),
+        ];
+
+        assert_eq!(plugin_order(&plugins).unwrap(), vec![1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn missing_dependency() {
+        let plugins = vec![plugin("render", &["window"])];
+        assert!(plugin_order(&plugins).is_err());
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut plugins = vec![plugin("vulkan", &[]), plugin("window", &[])];
+        assert!(plugin_order(&plugins).is_ok());
+
+        plugins.push(Box::new(Named {
+            name: "gl",
+            dependencies: vec!["window"],
+            conflicts: vec!["vulkan"],
+        }));
+        assert!(plugin_order(&plugins).is_err());
+    }
+
+    #[test]
+    fn cycle() {
+        let plugins = vec![
+            plugin("window", &[]),
+            plugin("a", &["b"]),
+            plugin("b", &["c"]),
+            plugin("c", &["a"]),
+        ];
+
+        let error = plugin_order(&plugins).unwrap_err().to_string();
+        assert!(error.contains("`a`, `b`, `c`"), "{}", error);
+    }
+}
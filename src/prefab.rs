@@ -0,0 +1,450 @@
+//! Prefabs, kinds of entities such as mobs or dropped items,
+//! defined in RON files and spawned through the `SceneRegistry`.
+//!
+//! A prefab lists the values of its components by the name they were
+//! registered under with `SceneRegistry::register_prefab`,
+//! and may inherit the components of a parent, e.g.
+//! ```ron
+//! (
+//!     parent: Some("mob"),
+//!     components: {
+//!         "health": (20),
+//!     },
+//!     remove: ["ai"],
+//! )
+//! ```
+use crate::{
+    core::ecs::{storage::Component, systems::CommandBuffer, world::Entry, *},
+    scene::SceneRegistry,
+};
+use anyhow::{bail, Context, Result};
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, MapAccess, Visitor},
+    Deserialize,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::Path,
+};
+
+/// A component value of a prefab, cloned into every spawned entity.
+pub trait PrefabComponent: Send + Sync {
+    fn add_to(&self, entry: &mut Entry);
+
+    fn add_with(&self, cmd: &mut CommandBuffer, entity: Entity);
+}
+
+impl<T: Component + Clone> PrefabComponent for T {
+    fn add_to(&self, entry: &mut Entry) {
+        entry.add_component(self.clone());
+    }
+
+    fn add_with(&self, cmd: &mut CommandBuffer, entity: Entity) {
+        cmd.add_component(entity, self.clone());
+    }
+}
+
+pub(crate) type LoadComponentFn = fn(
+    &mut dyn erased_serde::Deserializer,
+) -> Result<Box<dyn PrefabComponent>, erased_serde::Error>;
+
+pub(crate) fn load_component<T>(
+    d: &mut dyn erased_serde::Deserializer,
+) -> Result<Box<dyn PrefabComponent>, erased_serde::Error>
+where
+    T: Component + Clone + DeserializeOwned,
+{
+    Ok(Box::new(erased_serde::deserialize::<T>(d)?))
+}
+
+/// The definition of a prefab.
+#[derive(Default)]
+pub struct Prefab {
+    /// Prefab whose components are inherited.
+    pub parent: Option<String>,
+    /// Components by registered name, overriding the parent's.
+    pub components: BTreeMap<String, Box<dyn PrefabComponent>>,
+    /// Components of the parent left out, by registered name.
+    pub remove: Vec<String>,
+}
+
+impl Prefab {
+    /// Reads a prefab from RON, its components being
+    /// deserialized as registered in `registry`.
+    pub fn from_ron(registry: &SceneRegistry, source: &str) -> Result<Self> {
+        let mut de = ron::Deserializer::from_str(source)?;
+        let prefab = PrefabSeed { registry }.deserialize(&mut de)?;
+        de.end()?;
+
+        Ok(prefab)
+    }
+}
+
+impl fmt::Debug for Prefab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Prefab")
+            .field("parent", &self.parent)
+            .field("components", &self.components.keys().collect::<Vec<_>>())
+            .field("remove", &self.remove)
+            .finish()
+    }
+}
+
+/// A resource holding prefabs by name.
+#[derive(Debug, Default)]
+pub struct Prefabs {
+    prefabs: HashMap<String, Prefab>,
+}
+
+impl Prefabs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a prefab, replacing the previous one of the same name.
+    pub fn insert(&mut self, name: &str, prefab: Prefab) {
+        self.prefabs.insert(name.to_owned(), prefab);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.prefabs.contains_key(name)
+    }
+
+    /// Adds a prefab from a RON file, named after the file,
+    /// e.g. `zombie` for `prefabs/zombie.ron`.
+    pub fn load_file<P: AsRef<Path>>(&mut self, registry: &SceneRegistry, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let name = match path.file_stem().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => bail!("invalid prefab file name {:?}", path),
+        };
+
+        let source = std::fs::read_to_string(path)?;
+        let prefab =
+            Prefab::from_ron(registry, &source).with_context(|| format!("in {:?}", path))?;
+
+        self.insert(name, prefab);
+        Ok(())
+    }
+
+    /// Adds every `.ron` prefab file of a directory.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, registry: &SceneRegistry, dir: P) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.extension().map_or(false, |e| e == "ron") {
+                self.load_file(registry, &path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Components of a prefab along with the inherited ones,
+    /// children overriding their parents.
+    pub fn resolve(&self, name: &str) -> Result<Vec<&dyn PrefabComponent>> {
+        let mut chain = Vec::new();
+        let mut next = Some(name);
+
+        while let Some(name) = next {
+            if chain.iter().any(|&(n, _)| n == name) {
+                bail!("prefab `{}` inherits from itself", name);
+            }
+
+            let prefab = match self.prefabs.get(name) {
+                Some(prefab) => prefab,
+                None => bail!("unknown prefab `{}`", name),
+            };
+
+            chain.push((name, prefab));
+            next = prefab.parent.as_deref();
+        }
+
+        let mut components: BTreeMap<&str, &dyn PrefabComponent> = BTreeMap::new();
+        for (_, prefab) in chain.into_iter().rev() {
+            for removed in prefab.remove.iter() {
+                components.remove(removed.as_str());
+            }
+
+            for (name, c) in prefab.components.iter() {
+                components.insert(name, c.as_ref());
+            }
+        }
+
+        Ok(components.into_iter().map(|(_, c)| c).collect())
+    }
+
+    /// Spawns an entity with the components of a prefab.
+    pub fn spawn(&self, world: &mut World, name: &str) -> Result<Entity> {
+        let components = self.resolve(name)?;
+
+        let entity = world.push(());
+        let mut entry = world.entry(entity).unwrap();
+        for c in components {
+            c.add_to(&mut entry);
+        }
+
+        Ok(entity)
+    }
+
+    /// Spawns an entity with the components of a prefab
+    /// once the commands are flushed, e.g. from a system.
+    pub fn spawn_with(&self, cmd: &mut CommandBuffer, name: &str) -> Result<Entity> {
+        let components = self.resolve(name)?;
+
+        let entity = cmd.push(());
+        for c in components {
+            c.add_with(cmd, entity);
+        }
+
+        Ok(entity)
+    }
+}
+
+const FIELDS: &[&str] = &["parent", "components", "remove"];
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum Field {
+    Parent,
+    Components,
+    Remove,
+}
+
+struct PrefabSeed<'a> {
+    registry: &'a SceneRegistry,
+}
+
+impl<'de> DeserializeSeed<'de> for PrefabSeed<'_> {
+    type Value = Prefab;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Prefab, D::Error> {
+        deserializer.deserialize_struct("Prefab", FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for PrefabSeed<'_> {
+    type Value = Prefab;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a prefab")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Prefab, A::Error> {
+        let mut prefab = Prefab::default();
+
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Parent => prefab.parent = map.next_value()?,
+                Field::Remove => {
+                    prefab.remove = map.next_value()?;
+
+                    let registry = self.registry;
+                    if let Some(name) = prefab
+                        .remove
+                        .iter()
+                        .find(|&n| !registry.prefab_components.contains_key(n))
+                    {
+                        return Err(de::Error::custom(unregistered(name)));
+                    }
+                }
+                Field::Components => {
+                    prefab.components = map.next_value_seed(ComponentsSeed {
+                        registry: self.registry,
+                    })?
+                }
+            }
+        }
+
+        Ok(prefab)
+    }
+}
+
+struct ComponentsSeed<'a> {
+    registry: &'a SceneRegistry,
+}
+
+impl<'de> DeserializeSeed<'de> for ComponentsSeed<'_> {
+    type Value = BTreeMap<String, Box<dyn PrefabComponent>>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ComponentsSeed<'_> {
+    type Value = BTreeMap<String, Box<dyn PrefabComponent>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a map of components")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut components = BTreeMap::new();
+
+        while let Some(name) = map.next_key::<String>()? {
+            let load = match self.registry.prefab_components.get(&name) {
+                Some(&load) => load,
+                None => return Err(de::Error::custom(unregistered(&name))),
+            };
+
+            let component = map.next_value_seed(ComponentSeed { load })?;
+            components.insert(name, component);
+        }
+
+        Ok(components)
+    }
+}
+
+fn unregistered(name: &str) -> String {
+    format!("component `{}` isn't registered for prefabs", name)
+}
+
+struct ComponentSeed {
+    load: LoadComponentFn,
+}
+
+impl<'de> DeserializeSeed<'de> for ComponentSeed {
+    type Value = Box<dyn PrefabComponent>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
+        (self.load)(&mut erased).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Speed(f32);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Ai(bool);
+
+    /// Saved in scenes, but can't be cloned into prefabs.
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Inventory(Vec<u32>);
+
+    fn registry() -> SceneRegistry {
+        let mut registry = SceneRegistry::new();
+        registry
+            .register_prefab::<Health>("health")
+            .register_prefab::<Speed>("speed")
+            .register_prefab::<Ai>("ai")
+            .register::<Inventory>("inventory");
+        registry
+    }
+
+    fn prefabs(sources: &[(&str, &str)]) -> Prefabs {
+        let registry = registry();
+        let mut prefabs = Prefabs::new();
+        for (name, source) in sources.iter() {
+            prefabs.insert(name, Prefab::from_ron(&registry, source).unwrap());
+        }
+
+        prefabs
+    }
+
+    fn mobs() -> Prefabs {
+        prefabs(&[
+            ("mob", r#"(components: { "health": (20), "ai": (true) })"#),
+            (
+                "zombie",
+                r#"(parent: Some("mob"), components: { "health": (30), "speed": (1.5) })"#,
+            ),
+            (
+                "statue",
+                r#"(parent: Some("mob"), remove: ["ai"], components: {})"#,
+            ),
+            // Added back after the parent's was removed
+            (
+                "golem",
+                r#"(parent: Some("statue"), components: { "ai": (false) })"#,
+            ),
+        ])
+    }
+
+    fn component<T: Component + Copy>(world: &World, entity: Entity) -> Option<T> {
+        world
+            .entry_ref(entity)
+            .unwrap()
+            .get_component::<T>()
+            .ok()
+            .copied()
+    }
+
+    #[test]
+    fn inheritance_and_overrides() {
+        let prefabs = mobs();
+        let mut world = World::default();
+
+        let zombie = prefabs.spawn(&mut world, "zombie").unwrap();
+        assert_eq!(component(&world, zombie), Some(Health(30)));
+        assert_eq!(component(&world, zombie), Some(Speed(1.5)));
+        assert_eq!(component(&world, zombie), Some(Ai(true)));
+
+        let mut cmd = CommandBuffer::new(&world);
+        let mob = prefabs.spawn_with(&mut cmd, "mob").unwrap();
+        cmd.flush(&mut world);
+        assert_eq!(component(&world, mob), Some(Health(20)));
+        assert_eq!(component::<Speed>(&world, mob), None);
+    }
+
+    #[test]
+    fn remove() {
+        let prefabs = mobs();
+        let mut world = World::default();
+
+        let statue = prefabs.spawn(&mut world, "statue").unwrap();
+        assert_eq!(component(&world, statue), Some(Health(20)));
+        assert_eq!(component::<Ai>(&world, statue), None);
+
+        let golem = prefabs.spawn(&mut world, "golem").unwrap();
+        assert_eq!(component(&world, golem), Some(Ai(false)));
+    }
+
+    #[test]
+    fn cycles() {
+        let prefabs = prefabs(&[
+            ("a", r#"(parent: Some("b"))"#),
+            ("b", r#"(parent: Some("c"))"#),
+            ("c", r#"(parent: Some("a"))"#),
+            ("self", r#"(parent: Some("self"))"#),
+            ("orphan", r#"(parent: Some("missing"))"#),
+        ]);
+
+        assert!(prefabs.resolve("a").is_err());
+        assert!(prefabs.resolve("self").is_err());
+        assert!(prefabs.resolve("orphan").is_err());
+        assert!(prefabs.resolve("missing").is_err());
+        assert!(prefabs.spawn(&mut World::default(), "a").is_err());
+    }
+
+    #[test]
+    fn unregistered_components() {
+        let registry = registry();
+
+        assert!(Prefab::from_ron(&registry, r#"(components: { "wings": (2) })"#).is_err());
+        assert!(Prefab::from_ron(&registry, r#"(remove: ["wings"])"#).is_err());
+        // Not registered for prefabs
+        assert!(Prefab::from_ron(&registry, r#"(components: { "inventory": ([1]) })"#).is_err());
+        assert!(Prefab::from_ron(&registry, r#"(remove: ["inventory"])"#).is_err());
+    }
+}
@@ -0,0 +1,531 @@
+//! Scenes, entities of a `World` and some `Resources`
+//! saved to RON or a compact binary format.
+use crate::{
+    core::ecs::{
+        query::LayoutFilter,
+        serialize::{Canon, UnknownType},
+        storage::Component,
+        systems::Resource,
+        *,
+    },
+    prefab::{load_component, LoadComponentFn},
+};
+use anyhow::{Context, Result};
+use bincode::Options;
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeTuple},
+    Deserializer, Serialize, Serializer,
+};
+use std::{collections::BTreeMap, fmt};
+
+/// A marker component for entities that should stay out of scenes,
+/// saved with the filter `!component::<NonPersistent>()`.
+///
+/// Components are only saved if registered, so that e.g. GPU handles
+/// are left out of scenes by not registering them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NonPersistent;
+
+type SaveFn = fn(&Resources, &mut dyn FnMut(&dyn erased_serde::Serialize));
+type LoadFn =
+    fn(&mut Resources, &mut dyn erased_serde::Deserializer) -> Result<(), erased_serde::Error>;
+
+struct ResourceFns {
+    contains: fn(&Resources) -> bool,
+    save: SaveFn,
+    load: LoadFn,
+}
+
+fn contains<R: Resource>(r: &Resources) -> bool {
+    r.contains::<R>()
+}
+
+fn save<R: Resource + Serialize>(r: &Resources, f: &mut dyn FnMut(&dyn erased_serde::Serialize)) {
+    if let Some(res) = r.get::<R>() {
+        f(&*res);
+    }
+}
+
+fn load<R: Resource + DeserializeOwned>(
+    r: &mut Resources,
+    d: &mut dyn erased_serde::Deserializer,
+) -> Result<(), erased_serde::Error> {
+    r.insert::<R>(erased_serde::deserialize(d)?);
+    Ok(())
+}
+
+fn bin_options() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+/// The components and resources saved in scenes, under a stable name,
+/// components registered with [`register_prefab`](SceneRegistry::register_prefab)
+/// being usable in prefabs as well.
+pub struct SceneRegistry {
+    components: Registry<String>,
+    pub(crate) prefab_components: BTreeMap<String, LoadComponentFn>,
+    resources: BTreeMap<String, ResourceFns>,
+}
+
+impl Default for SceneRegistry {
+    fn default() -> Self {
+        let mut components = Registry::default();
+        components.on_unknown(UnknownType::Ignore);
+
+        Self {
+            components,
+            prefab_components: BTreeMap::new(),
+            resources: BTreeMap::new(),
+        }
+    }
+}
+
+impl SceneRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Saves components of type `T` under `name`.
+    pub fn register<T>(&mut self, name: &str) -> &mut Self
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        self.components.register::<T>(name.to_owned());
+        self
+    }
+
+    /// Saves components of type `T` under `name`, as [`register`](SceneRegistry::register)
+    /// does, and lets prefabs list them under the same name.
+    pub fn register_prefab<T>(&mut self, name: &str) -> &mut Self
+    where
+        T: Component + Clone + Serialize + DeserializeOwned,
+    {
+        self.register::<T>(name);
+        self.prefab_components
+            .insert(name.to_owned(), load_component::<T>);
+        self
+    }
+
+    /// Saves the resource of type `R` under `name`, if it exists.
+    pub fn register_resource<R>(&mut self, name: &str) -> &mut Self
+    where
+        R: Resource + Serialize + DeserializeOwned,
+    {
+        let fns = ResourceFns {
+            contains: contains::<R>,
+            save: save::<R>,
+            load: load::<R>,
+        };
+
+        if self.resources.insert(name.to_owned(), fns).is_some() {
+            log::warn!("scene resource `{}` registered twice", name);
+        }
+        self
+    }
+
+    /// Saves the entities matching `filter` and the registered resources to RON.
+    pub fn save_ron<F: LayoutFilter>(
+        &self,
+        world: &World,
+        resources: &Resources,
+        filter: F,
+    ) -> Result<String> {
+        let canon = Canon::default();
+        let scene = SceneSer {
+            world: world.as_serializable(filter, &self.components, &canon),
+            resources: ResourcesSer {
+                registry: self,
+                resources,
+            },
+        };
+
+        Ok(ron::ser::to_string_pretty(
+            &scene,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    /// Saves the entities matching `filter` and the registered resources
+    /// to the binary format.
+    pub fn save_bin<F: LayoutFilter>(
+        &self,
+        world: &World,
+        resources: &Resources,
+        filter: F,
+    ) -> Result<Vec<u8>> {
+        let canon = Canon::default();
+        let scene = SceneSer {
+            world: world.as_serializable(filter, &self.components, &canon),
+            resources: ResourcesSer {
+                registry: self,
+                resources,
+            },
+        };
+
+        Ok(bin_options().serialize(&scene)?)
+    }
+
+    /// Adds the entities of a RON scene to `world`, and inserts its resources.
+    ///
+    /// Loaded entities are new ones, the entities referenced
+    /// by their components are remapped accordingly.
+    /// Unregistered components and resources are skipped.
+    pub fn load_ron(
+        &self,
+        world: &mut World,
+        resources: &mut Resources,
+        scene: &str,
+    ) -> Result<()> {
+        let canon = Canon::default();
+        let mut de = ron::Deserializer::from_str(scene)?;
+
+        SceneSeed::new(self, world, resources, &canon)
+            .deserialize(&mut de)
+            .context("invalid scene")?;
+        de.end()?;
+
+        Ok(())
+    }
+
+    /// Adds the entities of a binary scene to `world`, and inserts its resources,
+    /// see [`load_ron`](SceneRegistry::load_ron).
+    ///
+    /// The binary format can't skip values, so unlike RON scenes,
+    /// scenes holding unregistered components or resources are rejected.
+    pub fn load_bin(
+        &self,
+        world: &mut World,
+        resources: &mut Resources,
+        scene: &[u8],
+    ) -> Result<()> {
+        let canon = Canon::default();
+
+        bin_options()
+            .deserialize_seed(SceneSeed::new(self, world, resources, &canon), scene)
+            .context("invalid scene")?;
+
+        Ok(())
+    }
+}
+
+/// A scene as written, the world then the resources.
+struct SceneSer<'a, W> {
+    world: W,
+    resources: ResourcesSer<'a>,
+}
+
+impl<W: Serialize> Serialize for SceneSer<'_, W> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&self.world)?;
+        tuple.serialize_element(&self.resources)?;
+        tuple.end()
+    }
+}
+
+/// The registered resources, as a map of their names to their values.
+struct ResourcesSer<'a> {
+    registry: &'a SceneRegistry,
+    resources: &'a Resources,
+}
+
+impl Serialize for ResourcesSer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let saved: Vec<_> = self
+            .registry
+            .resources
+            .iter()
+            .filter(|(_, fns)| (fns.contains)(self.resources))
+            .collect();
+
+        let mut map = serializer.serialize_map(Some(saved.len()))?;
+        for (name, fns) in saved {
+            let mut result = Ok(());
+            (fns.save)(self.resources, &mut |value| {
+                result = map.serialize_entry(name, value);
+            });
+            result?;
+        }
+        map.end()
+    }
+}
+
+/// Reads a scene, adding its entities to an existing world.
+struct SceneSeed<'a> {
+    registry: &'a SceneRegistry,
+    world: &'a mut World,
+    resources: &'a mut Resources,
+    canon: &'a Canon,
+}
+
+impl<'a> SceneSeed<'a> {
+    fn new(
+        registry: &'a SceneRegistry,
+        world: &'a mut World,
+        resources: &'a mut Resources,
+        canon: &'a Canon,
+    ) -> Self {
+        Self {
+            registry,
+            world,
+            resources,
+            canon,
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for SceneSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_tuple(2, self)
+    }
+}
+
+impl<'de> Visitor<'de> for SceneSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a world and its resources")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let world = self
+            .registry
+            .components
+            .as_deserialize_into_world(self.world, self.canon);
+        seq.next_element_seed(world)?
+            .ok_or_else(|| de::Error::invalid_length(0, &"a world and its resources"))?;
+
+        let resources = ResourcesSeed {
+            registry: self.registry,
+            resources: self.resources,
+        };
+        seq.next_element_seed(resources)?
+            .ok_or_else(|| de::Error::invalid_length(1, &"a world and its resources"))
+    }
+}
+
+/// Reads the registered resources, inserting them.
+struct ResourcesSeed<'a> {
+    registry: &'a SceneRegistry,
+    resources: &'a mut Resources,
+}
+
+impl<'de> DeserializeSeed<'de> for ResourcesSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ResourcesSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a map of resources")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(name) = map.next_key::<String>()? {
+            let fns = match self.registry.resources.get(&name) {
+                Some(fns) => fns,
+                None => {
+                    log::warn!("unknown scene resource `{}`, skipped", name);
+                    map.next_value::<de::IgnoredAny>()?;
+                    continue;
+                }
+            };
+
+            map.next_value_seed(ResourceSeed {
+                fns,
+                resources: &mut *self.resources,
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+struct ResourceSeed<'a> {
+    fns: &'a ResourceFns,
+    resources: &'a mut Resources,
+}
+
+impl<'de> DeserializeSeed<'de> for ResourceSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
+        (self.fns.load)(self.resources, &mut erased).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    /// References another entity of the scene.
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Target(Entity);
+
+    /// Never registered, e.g. a GPU handle.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Gpu(u32);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Score(u32);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    struct Seed(u64);
+
+    fn registry() -> SceneRegistry {
+        let mut registry = SceneRegistry::new();
+        registry
+            .register::<Health>("health")
+            .register::<Target>("target")
+            .register_resource::<Score>("score");
+        registry
+    }
+
+    /// A boss, its minion and a few entities left out of scenes.
+    fn scene() -> (World, Resources) {
+        let mut world = World::default();
+        let boss = world.push((Health(50),));
+        world.push((Health(10), Target(boss), Gpu(1)));
+        world.push((Health(99), NonPersistent));
+
+        let mut resources = Resources::default();
+        resources.insert(Score(7));
+        (world, resources)
+    }
+
+    fn get<T: Component + Copy>(world: &World, entity: Entity) -> Option<T> {
+        world
+            .entry_ref(entity)
+            .unwrap()
+            .get_component::<T>()
+            .ok()
+            .copied()
+    }
+
+    fn healths(world: &World) -> Vec<u32> {
+        let mut healths: Vec<u32> = <&Health>::query().iter(world).map(|h| h.0).collect();
+        healths.sort_unstable();
+        healths
+    }
+
+    /// Checks a loaded copy of `scene()`.
+    fn check(world: &World, resources: &Resources) {
+        assert_eq!(healths(world), vec![10, 50]);
+        assert_eq!(<&Gpu>::query().iter(world).count(), 0);
+        assert_eq!(<&NonPersistent>::query().iter(world).count(), 0);
+        assert_eq!(resources.get::<Score>().map(|s| *s), Some(Score(7)));
+
+        let (minion, target) = <(Entity, &Target)>::query()
+            .iter(world)
+            .map(|(e, t)| (*e, t.0))
+            .next()
+            .unwrap();
+        assert_eq!(get(world, minion), Some(Health(10)));
+        assert_eq!(get(world, target), Some(Health(50)));
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        let registry = registry();
+        let (world, resources) = scene();
+        let ron = registry
+            .save_ron(&world, &resources, !component::<NonPersistent>())
+            .unwrap();
+
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        registry.load_ron(&mut world, &mut resources, &ron).unwrap();
+        check(&world, &resources);
+    }
+
+    #[test]
+    fn bin_round_trip() {
+        let registry = registry();
+        let (world, resources) = scene();
+        let bin = registry
+            .save_bin(&world, &resources, !component::<NonPersistent>())
+            .unwrap();
+
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        registry.load_bin(&mut world, &mut resources, &bin).unwrap();
+        check(&world, &resources);
+    }
+
+    #[test]
+    fn entities_are_remapped() {
+        let registry = registry();
+        let (world, resources) = scene();
+        let ron = registry
+            .save_ron(&world, &resources, !component::<NonPersistent>())
+            .unwrap();
+
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let existing = world.push((Health(1),));
+        registry.load_ron(&mut world, &mut resources, &ron).unwrap();
+        registry.load_ron(&mut world, &mut resources, &ron).unwrap();
+
+        assert_eq!(healths(&world), vec![1, 10, 10, 50, 50]);
+
+        let targets: Vec<Entity> = <&Target>::query().iter(&world).map(|t| t.0).collect();
+        assert_eq!(targets.len(), 2);
+        assert_ne!(targets[0], targets[1]);
+
+        for &target in targets.iter() {
+            assert_ne!(target, existing);
+            assert_eq!(get(&world, target), Some(Health(50)));
+        }
+    }
+
+    #[test]
+    fn unknown_types() {
+        let mut saving = registry();
+        saving.register_resource::<Seed>("seed");
+
+        let (world, mut resources) = scene();
+        resources.insert(Seed(42));
+        let ron = saving
+            .save_ron(&world, &resources, !component::<NonPersistent>())
+            .unwrap();
+        let bin = saving
+            .save_bin(&world, &resources, !component::<NonPersistent>())
+            .unwrap();
+
+        // Unknown resources and components are skipped by RON scenes
+        let mut loading = SceneRegistry::new();
+        loading.register::<Health>("health");
+        loading.register_resource::<Score>("score");
+
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        loading.load_ron(&mut world, &mut resources, &ron).unwrap();
+        assert_eq!(healths(&world), vec![10, 50]);
+        assert_eq!(<&Target>::query().iter(&world).count(), 0);
+        assert_eq!(resources.get::<Score>().map(|s| *s), Some(Score(7)));
+        assert!(!resources.contains::<Seed>());
+
+        // and rejected by binary ones
+        let mut loading = registry();
+        let load_bin = |registry: &SceneRegistry| {
+            registry.load_bin(&mut World::default(), &mut Resources::default(), &bin)
+        };
+        assert!(load_bin(&loading).is_err());
+
+        loading.register_resource::<Seed>("seed");
+        assert!(load_bin(&loading).is_ok());
+    }
+}
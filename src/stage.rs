@@ -0,0 +1,229 @@
+//! Named stages of a frame, each compiled to its own `Schedule`,
+//! so that commands of a stage are flushed before the next one runs.
+use crate::core::ecs::{systems::Builder, *};
+use rayon::ThreadPool;
+use std::ops::Range;
+
+/// A stage of the application, frame stages run in order every frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Runs once, before the initial state is entered.
+    Startup,
+    /// Runs once, after the last frame, e.g. to flush saves.
+    Shutdown,
+    /// Window events and input.
+    PreUpdate,
+    /// Gameplay, preceded by the fixed ticks and followed by the current state.
+    Update,
+    PostUpdate,
+    /// Runs last, with `Interpolation` up to date.
+    Render,
+    /// A stage added by the user, see
+    /// [`AppBuilder::add_stage_after`](crate::app::AppBuilder::add_stage_after).
+    Custom(&'static str),
+}
+
+/// Builders of every stage, in order.
+pub(crate) struct Stages {
+    startup: Builder,
+    shutdown: Builder,
+    frame: Vec<(Stage, Builder)>,
+}
+
+impl Default for Stages {
+    fn default() -> Self {
+        use Stage::*;
+
+        Self {
+            startup: Schedule::builder(),
+            shutdown: Schedule::builder(),
+            frame: vec![PreUpdate, Update, PostUpdate, Render]
+                .into_iter()
+                .map(|s| (s, Schedule::builder()))
+                .collect(),
+        }
+    }
+}
+
+impl Stages {
+    /// ## Panics
+    /// * If `stage` was never added.
+    pub fn get_mut(&mut self, stage: Stage) -> &mut Builder {
+        match stage {
+            Stage::Startup => return &mut self.startup,
+            Stage::Shutdown => return &mut self.shutdown,
+            _ => {}
+        }
+
+        match self.frame.iter_mut().find(|(s, _)| *s == stage) {
+            Some((_, b)) => b,
+            None => panic!("stage `{:?}` was never added", stage),
+        }
+    }
+
+    /// Adds `stage` next to `anchor`, `offset` being `0`
+    /// to add it before and `1` after.
+    ///
+    /// ## Panics
+    /// * If `stage` already exists.
+    /// * If `anchor` isn't a frame stage.
+    pub fn insert(&mut self, stage: Stage, anchor: Stage, offset: usize) {
+        let once = stage == Stage::Startup || stage == Stage::Shutdown;
+        if once || self.frame.iter().any(|(s, _)| *s == stage) {
+            panic!("stage `{:?}` already exists", stage);
+        }
+
+        let i = match self.frame.iter().position(|(s, _)| *s == anchor) {
+            Some(i) => i,
+            None => panic!("`{:?}` is not a frame stage", anchor),
+        };
+
+        self.frame.insert(i + offset, (stage, Schedule::builder()));
+    }
+
+    pub fn build(self) -> StageSchedules {
+        StageSchedules {
+            startup: self.startup.into(),
+            shutdown: self.shutdown.into(),
+            frame: self
+                .frame
+                .into_iter()
+                .map(|(s, mut b)| (s, b.build()))
+                .collect(),
+        }
+    }
+}
+
+/// The schedule of every stage.
+pub(crate) struct StageSchedules {
+    startup: Schedule,
+    shutdown: Schedule,
+    frame: Vec<(Stage, Schedule)>,
+}
+
+impl StageSchedules {
+    pub fn startup(&mut self, w: &mut World, r: &mut Resources, pool: &ThreadPool) {
+        self.startup.execute_in_thread_pool(w, r, pool);
+    }
+
+    pub fn shutdown(&mut self, w: &mut World, r: &mut Resources, pool: &ThreadPool) {
+        self.shutdown.execute_in_thread_pool(w, r, pool);
+    }
+
+    /// Index of `Update` among the frame stages.
+    pub fn update_index(&self) -> usize {
+        self.frame
+            .iter()
+            .position(|(s, _)| *s == Stage::Update)
+            .unwrap()
+    }
+
+    pub fn frame_len(&self) -> usize {
+        self.frame.len()
+    }
+
+    /// Runs the frame stages within `range`, in order.
+    pub fn run(
+        &mut self,
+        range: Range<usize>,
+        w: &mut World,
+        r: &mut Resources,
+        pool: &ThreadPool,
+    ) {
+        for (_, schedule) in self.frame[range].iter_mut() {
+            schedule.execute_in_thread_pool(w, r, pool);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::ThreadPoolBuilder;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Marker;
+
+    #[derive(Debug, Default)]
+    struct Seen(usize);
+
+    fn order(stages: &Stages) -> Vec<Stage> {
+        stages.frame.iter().map(|&(s, _)| s).collect()
+    }
+
+    #[test]
+    fn insert_order() {
+        use Stage::*;
+
+        let mut stages = Stages::default();
+        stages.insert(Custom("physics"), Update, 0);
+        stages.insert(Custom("ui"), Render, 0);
+        stages.insert(Custom("audio"), Render, 1);
+        stages.insert(Custom("late"), Update, 1);
+
+        assert_eq!(
+            order(&stages),
+            vec![
+                PreUpdate,
+                Custom("physics"),
+                Update,
+                Custom("late"),
+                PostUpdate,
+                Custom("ui"),
+                Render,
+                Custom("audio"),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a frame stage")]
+    fn unknown_anchor() {
+        Stages::default().insert(Stage::Custom("a"), Stage::Custom("b"), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a frame stage")]
+    fn startup_anchor() {
+        Stages::default().insert(Stage::Custom("a"), Stage::Startup, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "already exists")]
+    fn existing_stage() {
+        Stages::default().insert(Stage::Update, Stage::Render, 0);
+    }
+
+    #[test]
+    fn commands_flushed_between_stages() {
+        let mut stages = Stages::default();
+
+        stages
+            .get_mut(Stage::PreUpdate)
+            .add_system(SystemBuilder::new("Spawn").build(|cmd, _, _, _| {
+                cmd.push((Marker,));
+            }));
+
+        stages.get_mut(Stage::Update).add_system(
+            SystemBuilder::new("Count")
+                .write_resource::<Seen>()
+                .with_query(<&Marker>::query())
+                .build(|_, world, seen, query| {
+                    seen.0 = query.iter(world).count();
+                }),
+        );
+
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        resources.insert(Seen::default());
+        let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+
+        let mut schedules = stages.build();
+        let len = schedules.frame_len();
+        schedules.run(0..len, &mut world, &mut resources, &pool);
+
+        // Spawned in `PreUpdate`, already in the `World` during `Update`
+        assert_eq!(resources.get::<Seen>().unwrap().0, 1);
+        assert_eq!(world.len(), 1);
+    }
+}
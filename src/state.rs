@@ -0,0 +1,442 @@
+//! A stack of game states, e.g. a main menu, playing, or paused,
+//! each running its own `Schedule`.
+use crate::core::ecs::{systems::Builder, *};
+use rayon::ThreadPool;
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+};
+
+/// Transitions applied in a row before giving up,
+/// states requesting transitions when entered could loop forever.
+const MAX_TRANSITIONS: usize = 64;
+
+/// A state of the game, only its type is used to identify it.
+///
+/// If a state needs data, insert it in `Resources`
+/// from its enter schedule and remove it from its exit schedule.
+pub trait State: 'static {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct StateId {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl StateId {
+    fn of<S: State>() -> Self {
+        Self {
+            id: TypeId::of::<S>(),
+            name: type_name::<S>(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transition {
+    Push(StateId),
+    Pop,
+    Switch(StateId),
+    Reset(StateId),
+}
+
+/// A resource holding the stack of active states,
+/// only the state on top of it is updated.
+///
+/// Transitions are queued, then applied at the end of the frame.
+#[derive(Debug, Default)]
+pub struct StateStack {
+    stack: Vec<StateId>,
+    pending: Vec<Transition>,
+}
+
+impl StateStack {
+    /// Pauses the current state and enters `S`.
+    pub fn push<S: State>(&mut self) {
+        self.pending.push(Transition::Push(StateId::of::<S>()));
+    }
+
+    /// Exits the current state and resumes the one below.
+    pub fn pop(&mut self) {
+        self.pending.push(Transition::Pop);
+    }
+
+    /// Exits the current state and enters `S` in its place.
+    pub fn switch<S: State>(&mut self) {
+        self.pending.push(Transition::Switch(StateId::of::<S>()));
+    }
+
+    /// Exits every state, then enters `S`.
+    pub fn reset<S: State>(&mut self) {
+        self.pending.push(Transition::Reset(StateId::of::<S>()));
+    }
+
+    /// Whether `S` is the updated state.
+    pub fn is<S: State>(&self) -> bool {
+        self.stack.last().map(|s| s.id) == Some(TypeId::of::<S>())
+    }
+
+    /// Whether `S` is in the stack, updated or paused.
+    pub fn contains<S: State>(&self) -> bool {
+        self.stack.iter().any(|s| s.id == TypeId::of::<S>())
+    }
+
+    /// Type name of the updated state.
+    pub fn current(&self) -> Option<&'static str> {
+        self.stack.last().map(|s| s.name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+}
+
+/// Builders of the schedules of a state.
+pub struct StateBuilder {
+    /// Runs every frame while the state is on top of the stack.
+    pub update: Builder,
+    /// Runs every fixed tick while the state is on top of the stack.
+    pub fixed: Builder,
+    /// Runs once when the state is pushed.
+    pub enter: Builder,
+    /// Runs once when the state is popped.
+    pub exit: Builder,
+    /// Runs once when another state is pushed on top of this one.
+    pub pause: Builder,
+    /// Runs once when the state on top of this one is popped.
+    pub resume: Builder,
+}
+
+impl Default for StateBuilder {
+    fn default() -> Self {
+        Self {
+            update: Schedule::builder(),
+            fixed: Schedule::builder(),
+            enter: Schedule::builder(),
+            exit: Schedule::builder(),
+            pause: Schedule::builder(),
+            resume: Schedule::builder(),
+        }
+    }
+}
+
+struct StateSchedules {
+    update: Schedule,
+    fixed: Schedule,
+    enter: Schedule,
+    exit: Schedule,
+    pause: Schedule,
+    resume: Schedule,
+}
+
+impl From<StateBuilder> for StateSchedules {
+    fn from(mut b: StateBuilder) -> Self {
+        Self {
+            update: b.update.build(),
+            fixed: b.fixed.build(),
+            enter: b.enter.build(),
+            exit: b.exit.build(),
+            pause: b.pause.build(),
+            resume: b.resume.build(),
+        }
+    }
+}
+
+/// Every registered state and their schedules.
+#[derive(Default)]
+pub(crate) struct StateMachine {
+    builders: HashMap<TypeId, StateBuilder>,
+    states: HashMap<TypeId, StateSchedules>,
+}
+
+impl StateMachine {
+    pub fn builder<S: State>(&mut self) -> &mut StateBuilder {
+        self.builders.entry(TypeId::of::<S>()).or_default()
+    }
+
+    /// Builds the schedules of every state.
+    pub fn build(&mut self) {
+        for (id, b) in self.builders.drain() {
+            self.states.insert(id, b.into());
+        }
+    }
+
+    /// Runs the update schedule of the state on top of the stack.
+    pub fn update(&mut self, w: &mut World, r: &mut Resources, pool: &ThreadPool) {
+        if let Some(s) = self.top(r) {
+            s.update.execute_in_thread_pool(w, r, pool);
+        }
+    }
+
+    /// Runs the fixed schedule of the state on top of the stack.
+    pub fn fixed_update(&mut self, w: &mut World, r: &mut Resources, pool: &ThreadPool) {
+        if let Some(s) = self.top(r) {
+            s.fixed.execute_in_thread_pool(w, r, pool);
+        }
+    }
+
+    fn top(&mut self, r: &Resources) -> Option<&mut StateSchedules> {
+        let top = r
+            .get::<StateStack>()
+            .and_then(|s| s.stack.last().copied())?;
+        self.states.get_mut(&top.id)
+    }
+
+    /// Applies the queued transitions, running enter, exit,
+    /// pause and resume schedules.
+    pub fn apply_transitions(&mut self, w: &mut World, r: &mut Resources, pool: &ThreadPool) {
+        for _ in 0..MAX_TRANSITIONS {
+            let transition = match r.get_mut::<StateStack>() {
+                Some(mut stack) if !stack.pending.is_empty() => stack.pending.remove(0),
+                _ => return,
+            };
+
+            match transition {
+                Transition::Push(s) => {
+                    self.pause(w, r, pool);
+                    self.enter(s, w, r, pool);
+                }
+                Transition::Pop => {
+                    self.exit(w, r, pool);
+                    self.resume(w, r, pool);
+                }
+                Transition::Switch(s) => {
+                    self.exit(w, r, pool);
+                    self.enter(s, w, r, pool);
+                }
+                Transition::Reset(s) => {
+                    while r.get::<StateStack>().map_or(false, |s| !s.is_empty()) {
+                        self.exit(w, r, pool);
+                    }
+                    self.enter(s, w, r, pool);
+                }
+            }
+        }
+
+        if r.get::<StateStack>()
+            .map_or(false, |s| !s.pending.is_empty())
+        {
+            log::warn!(
+                "more than {} state transitions in a frame, the rest is delayed",
+                MAX_TRANSITIONS
+            );
+        }
+    }
+
+    fn enter(&mut self, s: StateId, w: &mut World, r: &mut Resources, pool: &ThreadPool) {
+        log::debug!("entering state `{}`", s.name);
+        r.get_mut::<StateStack>().unwrap().stack.push(s);
+
+        // A state without systems has no schedules
+        if let Some(schedules) = self.states.get_mut(&s.id) {
+            schedules.enter.execute_in_thread_pool(w, r, pool);
+        }
+    }
+
+    fn pause(&mut self, w: &mut World, r: &mut Resources, pool: &ThreadPool) {
+        if let Some(s) = self.top(r) {
+            s.pause.execute_in_thread_pool(w, r, pool);
+        }
+    }
+
+    fn resume(&mut self, w: &mut World, r: &mut Resources, pool: &ThreadPool) {
+        if let Some(s) = self.top(r) {
+            s.resume.execute_in_thread_pool(w, r, pool);
+        }
+    }
+
+    fn exit(&mut self, w: &mut World, r: &mut Resources, pool: &ThreadPool) {
+        let s = match r.get_mut::<StateStack>().and_then(|mut s| s.stack.pop()) {
+            Some(s) => s,
+            None => {
+                log::warn!("no state to exit");
+                return;
+            }
+        };
+
+        log::debug!("exiting state `{}`", s.name);
+        if let Some(schedules) = self.states.get_mut(&s.id) {
+            schedules.exit.execute_in_thread_pool(w, r, pool);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::systems::Runnable;
+    use rayon::ThreadPoolBuilder;
+
+    struct Menu;
+    impl State for Menu {}
+
+    struct Playing;
+    impl State for Playing {}
+
+    /// Registers no system.
+    struct Paused;
+    impl State for Paused {}
+
+    #[derive(Debug, Default)]
+    struct Log(Vec<&'static str>);
+
+    fn log_system(name: &'static str) -> impl Runnable {
+        SystemBuilder::new(name)
+            .write_resource::<Log>()
+            .build(move |_, _, log, _| log.0.push(name))
+    }
+
+    struct Harness {
+        world: World,
+        resources: Resources,
+        states: StateMachine,
+        pool: ThreadPool,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let mut resources = Resources::default();
+            resources.insert(StateStack::default());
+            resources.insert(Log::default());
+
+            let mut states = StateMachine::default();
+            let menu = states.builder::<Menu>();
+            menu.enter.add_system(log_system("enter menu"));
+            menu.exit.add_system(log_system("exit menu"));
+            menu.pause.add_system(log_system("pause menu"));
+            menu.resume.add_system(log_system("resume menu"));
+
+            let playing = states.builder::<Playing>();
+            playing.enter.add_system(log_system("enter playing"));
+            playing.exit.add_system(log_system("exit playing"));
+            playing.pause.add_system(log_system("pause playing"));
+            playing.resume.add_system(log_system("resume playing"));
+            states.build();
+
+            Self {
+                world: World::default(),
+                resources,
+                states,
+                pool: ThreadPoolBuilder::new().num_threads(1).build().unwrap(),
+            }
+        }
+
+        fn apply<F: FnOnce(&mut StateStack)>(&mut self, f: F) {
+            f(&mut *self.resources.get_mut::<StateStack>().unwrap());
+            self.states
+                .apply_transitions(&mut self.world, &mut self.resources, &self.pool);
+        }
+
+        fn stack(&self) -> (Option<&'static str>, usize) {
+            let stack = self.resources.get::<StateStack>().unwrap();
+            (stack.current(), stack.len())
+        }
+
+        fn log(&mut self) -> Vec<&'static str> {
+            std::mem::take(&mut self.resources.get_mut::<Log>().unwrap().0)
+        }
+    }
+
+    #[test]
+    fn push_pop_switch() {
+        let mut h = Harness::new();
+
+        h.apply(|s| s.push::<Menu>());
+        assert_eq!(h.stack(), (Some(type_name::<Menu>()), 1));
+        assert_eq!(h.log(), vec!["enter menu"]);
+
+        h.apply(|s| s.push::<Playing>());
+        assert_eq!(h.stack(), (Some(type_name::<Playing>()), 2));
+        assert_eq!(h.log(), vec!["pause menu", "enter playing"]);
+
+        h.apply(|s| s.pop());
+        assert_eq!(h.stack(), (Some(type_name::<Menu>()), 1));
+        assert_eq!(h.log(), vec!["exit playing", "resume menu"]);
+
+        h.apply(|s| s.switch::<Playing>());
+        assert_eq!(h.stack(), (Some(type_name::<Playing>()), 1));
+        assert_eq!(h.log(), vec!["exit menu", "enter playing"]);
+
+        h.apply(|s| s.pop());
+        assert_eq!(h.stack(), (None, 0));
+        assert_eq!(h.log(), vec!["exit playing"]);
+    }
+
+    #[test]
+    fn transitions_in_order() {
+        let mut h = Harness::new();
+
+        h.apply(|s| {
+            s.push::<Menu>();
+            s.push::<Playing>();
+            s.pop();
+            s.switch::<Playing>();
+        });
+        assert_eq!(h.stack(), (Some(type_name::<Playing>()), 1));
+        assert_eq!(
+            h.log(),
+            vec![
+                "enter menu",
+                "pause menu",
+                "enter playing",
+                "exit playing",
+                "resume menu",
+                "exit menu",
+                "enter playing",
+            ]
+        );
+
+        h.apply(|s| {
+            s.push::<Menu>();
+            s.reset::<Menu>();
+        });
+        assert_eq!(h.stack(), (Some(type_name::<Menu>()), 1));
+        assert_eq!(
+            h.log(),
+            vec![
+                "pause playing",
+                "enter menu",
+                "exit menu",
+                "exit playing",
+                "enter menu",
+            ]
+        );
+    }
+
+    #[test]
+    fn systemless_state() {
+        let mut h = Harness::new();
+
+        h.apply(|s| s.push::<Menu>());
+        h.apply(|s| s.push::<Paused>());
+        assert_eq!(h.stack(), (Some(type_name::<Paused>()), 2));
+
+        h.apply(|s| s.switch::<Playing>());
+        assert_eq!(h.stack(), (Some(type_name::<Playing>()), 2));
+
+        h.apply(|s| s.switch::<Paused>());
+        assert_eq!(h.stack(), (Some(type_name::<Paused>()), 2));
+
+        h.apply(|s| s.pop());
+        assert_eq!(h.stack(), (Some(type_name::<Menu>()), 1));
+
+        h.apply(|s| s.reset::<Paused>());
+        assert_eq!(h.stack(), (Some(type_name::<Paused>()), 1));
+
+        assert_eq!(
+            h.log(),
+            vec![
+                "enter menu",
+                "pause menu",
+                "enter playing",
+                "exit playing",
+                "resume menu",
+                "exit menu",
+            ]
+        );
+    }
+}
@@ -1,4 +1,9 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+/// Default no. of fixed ticks per second.
+pub const DEFAULT_TICK_RATE: u32 = 60;
+/// Default max no. of fixed ticks run in a single frame.
+pub const DEFAULT_MAX_TICKS: u32 = 8;
 
 /// Use to keep track of delay, either for a
 /// `System`'s state or as a component of an `Entity`.
@@ -30,6 +35,116 @@ impl Default for DeltaTime {
     }
 }
 
+/// A resource describing the fixed update, run at a constant rate
+/// whatever the frame rate, e.g. for physics or block ticks.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTime {
+    step: Duration,
+    /// Ticks run in a single frame at most, the time left behind is dropped
+    /// so that a slow frame doesn't make the next one even slower.
+    pub max_ticks: u32,
+    tick: u64,
+}
+
+impl FixedTime {
+    /// `tick_rate` ticks per second.
+    pub fn new(tick_rate: u32) -> Self {
+        Self {
+            step: Duration::from_secs_f64(1. / tick_rate.max(1) as f64),
+            max_ticks: DEFAULT_MAX_TICKS,
+            tick: 0,
+        }
+    }
+
+    /// Simulated time between two ticks.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Simulated time between two ticks, in seconds.
+    pub fn step_secs(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// No. of ticks run since the start, including the running one.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub(crate) fn next_tick(&mut self) {
+        self.tick += 1;
+    }
+}
+
+impl Default for FixedTime {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICK_RATE)
+    }
+}
+
+/// A resource holding how far the frame is between the last fixed tick
+/// and the next one, in `[0, 1)`, to interpolate what's rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, shrinkwraprs::Shrinkwrap)]
+pub struct Interpolation(pub f32);
+
+/// Accumulates the time of every frame, consuming it in fixed ticks.
+#[derive(Debug)]
+pub struct FixedClock {
+    last: Instant,
+    accumulator: Duration,
+}
+
+impl FixedClock {
+    /// Adds the time elapsed since the last call,
+    /// then returns the no. of ticks to run this frame.
+    pub fn advance(&mut self, fixed: &FixedTime) -> u32 {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+
+        self.advance_by(elapsed, fixed)
+    }
+
+    /// Adds `elapsed` whatever the time actually elapsed,
+    /// then returns the no. of ticks to run this frame.
+    pub fn advance_by(&mut self, elapsed: Duration, fixed: &FixedTime) -> u32 {
+        self.accumulator += elapsed;
+
+        let mut ticks = 0;
+        while self.accumulator >= fixed.step {
+            if ticks == fixed.max_ticks {
+                let rem = self.accumulator_rem(fixed);
+                log::debug!("fixed update behind, dropped {:?}", self.accumulator - rem);
+                self.accumulator = rem;
+                break;
+            }
+
+            self.accumulator -= fixed.step;
+            ticks += 1;
+        }
+
+        ticks
+    }
+
+    /// Progress towards the next tick, see [`Interpolation`](Interpolation).
+    pub fn alpha(&self, fixed: &FixedTime) -> f32 {
+        (self.accumulator.as_secs_f64() / fixed.step.as_secs_f64()) as f32
+    }
+
+    fn accumulator_rem(&self, fixed: &FixedTime) -> Duration {
+        Duration::from_nanos((self.accumulator.as_nanos() % fixed.step.as_nanos()) as u64)
+    }
+}
+
+impl Default for FixedClock {
+    fn default() -> Self {
+        Self {
+            last: Instant::now(),
+            accumulator: Duration::default(),
+        }
+    }
+}
+
 #[derive(Debug)]
 /// Counter that calculates the average tps over a collection of samples.
 pub struct TpsCounter {
@@ -114,3 +229,46 @@ impl<A> CircularBuffer<A> {
         out
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn assert_alpha(clock: &FixedClock, fixed: &FixedTime, alpha: f32) {
+        assert!((clock.alpha(fixed) - alpha).abs() < 1e-4);
+    }
+
+    #[test]
+    fn step_counting() {
+        let fixed = FixedTime::new(10);
+        let mut clock = FixedClock::default();
+        assert_eq!(fixed.step(), ms(100));
+
+        assert_eq!(clock.advance_by(ms(50), &fixed), 0);
+        assert_alpha(&clock, &fixed, 0.5);
+
+        assert_eq!(clock.advance_by(ms(60), &fixed), 1);
+        assert_alpha(&clock, &fixed, 0.1);
+
+        assert_eq!(clock.advance_by(ms(390), &fixed), 4);
+        assert_alpha(&clock, &fixed, 0.);
+    }
+
+    #[test]
+    fn max_ticks_clamp() {
+        let mut fixed = FixedTime::new(10);
+        fixed.max_ticks = 2;
+        let mut clock = FixedClock::default();
+
+        // The time left behind is dropped, but not the progress to the next tick
+        assert_eq!(clock.advance_by(ms(1035), &fixed), 2);
+        assert_alpha(&clock, &fixed, 0.35);
+
+        assert_eq!(clock.advance_by(ms(65), &fixed), 1);
+        assert_alpha(&clock, &fixed, 0.);
+    }
+}
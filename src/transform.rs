@@ -0,0 +1,418 @@
+//! Positions of entities relative to their parent, e.g. held items
+//! or mounted cameras, propagated to their `GlobalTransform`.
+//!
+//! Add `transform_routine` to a stage, usually `Stage::PostUpdate`,
+//! then use `GlobalTransform` as a camera's `View`. With the `gui` feature,
+//! entities that are `InstanceOf` a mesh also fill its `Instances`.
+#[cfg(feature = "gui")]
+use crate::gfx::{frustum::Instances, InstanceRaw};
+use crate::{
+    core::ecs::{systems::Builder, world::EntityAccessError, *},
+    gfx::camera::View,
+};
+use cgmath::{Matrix4, One, Quaternion, SquareMatrix, Vector3, Zero};
+use shrinkwraprs::*;
+use std::collections::HashMap;
+#[cfg(feature = "gui")]
+use std::collections::HashSet;
+
+/// Translation, rotation and scale of an entity, relative to its `Parent` if any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self {
+            translation,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quaternion<f32>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vector3<f32>) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Scales, then rotates, then translates.
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::zero(),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1., 1., 1.),
+        }
+    }
+}
+
+/// The entity an entity's `Transform` is relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct Parent(pub Entity);
+
+/// Entities whose `Parent` is this entity, kept up to date by `hierarchy_system`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Shrinkwrap)]
+pub struct Children(pub Vec<Entity>);
+
+/// The world matrix of an entity, computed from its `Transform` and its parents'.
+#[derive(Debug, Clone, Copy, PartialEq, Shrinkwrap)]
+pub struct GlobalTransform(pub Matrix4<f32>);
+
+impl GlobalTransform {
+    pub fn translation(&self) -> Vector3<f32> {
+        self.0.w.truncate()
+    }
+}
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self(Matrix4::identity())
+    }
+}
+
+impl From<Transform> for GlobalTransform {
+    fn from(t: Transform) -> Self {
+        Self(t.matrix())
+    }
+}
+
+/// Makes an entity an instance of the mesh whose `Instances`
+/// are held by another entity, drawn with its `GlobalTransform`.
+#[cfg(feature = "gui")]
+#[doc(cfg(feature = "gui"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstanceOf(pub Entity);
+
+/// Views from the entity, for cameras mounted with `Parent`.
+impl View for GlobalTransform {
+    fn matrix(&self) -> Matrix4<f32> {
+        self.0.invert().unwrap_or_else(Matrix4::identity)
+    }
+}
+
+/// Adds the hierarchy and transform systems, flushing after each,
+/// then the [`instance_system`](instance_system) with the `gui` feature.
+///
+/// Meant to be used with `routine_fn_in`, e.g.
+/// `routine_fn_in(Stage::PostUpdate, transform_routine)`.
+pub fn transform_routine(_: &mut World, _: &mut Resources, b: &mut Builder) {
+    b.add_system(hierarchy_system())
+        .flush()
+        .add_system(transform_system())
+        .flush();
+
+    #[cfg(feature = "gui")]
+    b.add_system(instance_system());
+}
+
+/// Rebuilds the `Children` of every parent from the `Parent` components.
+pub fn hierarchy_system() -> impl systems::Runnable {
+    SystemBuilder::new("HierarchySystem")
+        .with_query(<(Entity, &Parent)>::query())
+        .with_query(<(Entity, &mut Children)>::query())
+        .build(|cmd, world, _, (parents, children)| {
+            let mut map: HashMap<Entity, Vec<Entity>> = HashMap::new();
+            for (e, parent) in parents.iter(world) {
+                map.entry(parent.0).or_default().push(*e);
+            }
+
+            for (e, children) in children.iter_mut(world) {
+                let new = map.remove(e).unwrap_or_default();
+                if children.0 != new {
+                    children.0 = new;
+                }
+            }
+
+            for (parent, children) in map {
+                // Despawned, its children are roots until their `Parent` is removed
+                if let Err(EntityAccessError::EntityNotFound) = world.entry_ref(parent) {
+                    continue;
+                }
+
+                cmd.add_component(parent, Children(children));
+            }
+        })
+}
+
+struct Node {
+    local: Matrix4<f32>,
+    parent: Option<Entity>,
+    children: Vec<Entity>,
+}
+
+/// Computes the `GlobalTransform` of every entity with a `Transform`,
+/// from the roots down to their children.
+///
+/// Entities whose parent has no `Transform` or was despawned are roots,
+/// entities within a cycle of parents are left untouched.
+pub fn transform_system() -> impl systems::Runnable {
+    SystemBuilder::new("TransformSystem")
+        .with_query(<(Entity, &Transform, Option<&Parent>, Option<&Children>)>::query())
+        .with_query(<(Entity, &mut GlobalTransform)>::query())
+        .build(|cmd, world, _, (transforms, globals)| {
+            let nodes: HashMap<Entity, Node> = transforms
+                .iter(world)
+                .map(|(e, t, parent, children)| {
+                    let node = Node {
+                        local: t.matrix(),
+                        parent: parent.map(|p| p.0),
+                        children: children.map(|c| c.0.clone()).unwrap_or_default(),
+                    };
+
+                    (*e, node)
+                })
+                .collect();
+
+            let mut stack: Vec<(Entity, Matrix4<f32>)> = nodes
+                .iter()
+                .filter(|(_, n)| n.parent.map_or(true, |p| !nodes.contains_key(&p)))
+                .map(|(e, _)| (*e, Matrix4::identity()))
+                .collect();
+
+            let mut computed = HashMap::with_capacity(nodes.len());
+            while let Some((e, parent)) = stack.pop() {
+                if computed.contains_key(&e) {
+                    continue;
+                }
+
+                let node = &nodes[&e];
+                let global = parent * node.local;
+                computed.insert(e, global);
+
+                for child in node.children.iter() {
+                    // `Children` may be stale until the next `hierarchy_system`
+                    if nodes.get(child).and_then(|c| c.parent) == Some(e) {
+                        stack.push((*child, global));
+                    }
+                }
+            }
+
+            for (e, global) in globals.iter_mut(world) {
+                if let Some(m) = computed.remove(e) {
+                    global.0 = m;
+                }
+            }
+
+            for (e, m) in computed {
+                cmd.add_component(e, GlobalTransform(m));
+            }
+        })
+}
+
+/// Rewrites the `instances` of every `Instances` from the `GlobalTransform`
+/// of the entities that are `InstanceOf` it.
+///
+/// `Instances` no entity ever referred to are left untouched, e.g. filled by hand,
+/// and are cleared once their last instance is gone.
+#[cfg(feature = "gui")]
+#[doc(cfg(feature = "gui"))]
+pub fn instance_system() -> impl systems::Runnable {
+    let mut filled: HashSet<Entity> = HashSet::new();
+
+    SystemBuilder::new("InstanceSystem")
+        .with_query(<(&InstanceOf, &GlobalTransform)>::query())
+        .with_query(<(Entity, &mut Instances)>::query())
+        .build(move |_, world, _, (instanced, meshes)| {
+            let mut raw: HashMap<Entity, Vec<InstanceRaw>> = HashMap::new();
+            for (of, global) in instanced.iter(world) {
+                raw.entry(of.0).or_default().push(global.into());
+            }
+
+            let mut next = HashSet::with_capacity(raw.len());
+            for (e, instances) in meshes.iter_mut(world) {
+                match raw.remove(e) {
+                    Some(models) => {
+                        instances.instances = models;
+                        next.insert(*e);
+                    }
+                    None if filled.contains(e) => instances.instances.clear(),
+                    None => {}
+                }
+            }
+
+            filled = next;
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(world: &mut World) {
+        let mut resources = Resources::default();
+        let mut builder = Schedule::builder();
+        transform_routine(world, &mut resources, &mut builder);
+
+        builder.build().execute(world, &mut resources);
+    }
+
+    fn at(x: f32, y: f32, z: f32) -> Transform {
+        Transform::from_translation(Vector3::new(x, y, z))
+    }
+
+    fn global(world: &World, e: Entity) -> Option<Vector3<f32>> {
+        let entry = world.entry_ref(e).unwrap();
+        entry
+            .get_component::<GlobalTransform>()
+            .ok()
+            .map(GlobalTransform::translation)
+    }
+
+    fn children(world: &World, e: Entity) -> Vec<Entity> {
+        let entry = world.entry_ref(e).unwrap();
+        entry
+            .get_component::<Children>()
+            .map(|c| c.0.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn root() {
+        let mut world = World::default();
+        let root = world.push((at(1., 2., 3.),));
+
+        let despawned = world.push(());
+        world.remove(despawned);
+        let orphan = world.push((at(4., 5., 6.), Parent(despawned)));
+
+        run(&mut world);
+        assert_eq!(global(&world, root), Some(Vector3::new(1., 2., 3.)));
+        assert_eq!(global(&world, orphan), Some(Vector3::new(4., 5., 6.)));
+        assert!(!world.contains(despawned));
+    }
+
+    #[test]
+    fn nested_child() {
+        let mut world = World::default();
+        let root = world.push((at(1., 0., 0.).with_scale(Vector3::new(2., 2., 2.)),));
+        let child = world.push((at(1., 0., 0.), Parent(root)));
+        let grandchild = world.push((at(0., 1., 0.), Parent(child)));
+
+        run(&mut world);
+        assert_eq!(children(&world, root), vec![child]);
+        assert_eq!(children(&world, child), vec![grandchild]);
+        assert_eq!(global(&world, child), Some(Vector3::new(3., 0., 0.)));
+        assert_eq!(global(&world, grandchild), Some(Vector3::new(3., 2., 0.)));
+
+        // Moving the root moves its descendants
+        *world
+            .entry(root)
+            .unwrap()
+            .get_component_mut::<Transform>()
+            .unwrap() = at(0., 0., 0.);
+        run(&mut world);
+        assert_eq!(global(&world, grandchild), Some(Vector3::new(1., 1., 0.)));
+    }
+
+    #[test]
+    fn reparenting() {
+        let mut world = World::default();
+        let first = world.push((at(1., 0., 0.),));
+        let second = world.push((at(0., 0., 10.),));
+        let child = world.push((at(0., 1., 0.), Parent(first)));
+
+        run(&mut world);
+        assert_eq!(global(&world, child), Some(Vector3::new(1., 1., 0.)));
+
+        world.entry(child).unwrap().add_component(Parent(second));
+        run(&mut world);
+        assert!(children(&world, first).is_empty());
+        assert_eq!(children(&world, second), vec![child]);
+        assert_eq!(global(&world, child), Some(Vector3::new(0., 1., 10.)));
+
+        world.entry(child).unwrap().remove_component::<Parent>();
+        run(&mut world);
+        assert!(children(&world, second).is_empty());
+        assert_eq!(global(&world, child), Some(Vector3::new(0., 1., 0.)));
+    }
+
+    #[test]
+    fn parent_cycle() {
+        let mut world = World::default();
+        let a = world.push((at(1., 0., 0.),));
+        let b = world.push((at(0., 1., 0.), Parent(a)));
+        let c = world.push((at(0., 0., 1.), Parent(b)));
+        world.entry(a).unwrap().add_component(Parent(c));
+        let root = world.push((at(1., 1., 1.),));
+
+        run(&mut world);
+        for &e in [a, b, c].iter() {
+            assert_eq!(global(&world, e), None);
+        }
+        assert_eq!(global(&world, root), Some(Vector3::new(1., 1., 1.)));
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn instances() {
+        use crate::gfx::frustum::Aabb;
+        use cgmath::Point3;
+
+        let translations = |world: &World, e: Entity| {
+            let entry = world.entry_ref(e).unwrap();
+            let instances = entry.get_component::<Instances>().unwrap();
+            let mut translations: Vec<Vector3<f32>> = instances
+                .instances
+                .iter()
+                .map(|i| i.model().w.truncate())
+                .collect();
+
+            translations.sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap());
+            translations
+        };
+
+        // The same schedule every frame, as the system remembers the filled instances
+        let mut resources = Resources::default();
+        let mut builder = Schedule::builder();
+        transform_routine(&mut World::default(), &mut resources, &mut builder);
+        let mut schedule = builder.build();
+        let mut run = |world: &mut World| schedule.execute(world, &mut resources);
+
+        let mut world = World::default();
+        let bounds = Aabb::cube(Point3::new(0., 0., 0.), 1.);
+        let mesh = world.push((Instances::new(bounds, Vec::new()),));
+        let manual = world.push((Instances::new(
+            bounds,
+            vec![(&GlobalTransform::default()).into()],
+        ),));
+
+        let root = world.push((at(1., 0., 0.), InstanceOf(mesh)));
+        let held = world.push((at(0., 2., 0.), Parent(root), InstanceOf(mesh)));
+
+        run(&mut world);
+        assert_eq!(
+            translations(&world, mesh),
+            vec![Vector3::new(1., 0., 0.), Vector3::new(1., 2., 0.)]
+        );
+        assert_eq!(translations(&world, manual), vec![Vector3::new(0., 0., 0.)]);
+
+        world.remove(held);
+        run(&mut world);
+        assert_eq!(translations(&world, mesh), vec![Vector3::new(1., 0., 0.)]);
+
+        world.remove(root);
+        run(&mut world);
+        assert!(translations(&world, mesh).is_empty());
+        assert_eq!(translations(&world, manual), vec![Vector3::new(0., 0., 0.)]);
+    }
+}
@@ -0,0 +1,538 @@
+use super::{edit::WorldEdit, join_pos, split_pos, ChunkCoord, VoxelPos, VoxelWorld};
+use crate::{
+    chunk::{
+        bytes::{write_varint, ByteReader},
+        Accessor, Unit, UnitBytes,
+    },
+    core::{
+        ecs::{
+            systems::{Builder, CommandBuffer, Runnable},
+            *,
+        },
+        events::{new_channel, subscribe, EventChannel, ReaderId},
+    },
+};
+use anyhow::{ensure, Result};
+use shrinkwraprs::*;
+use std::{
+    any::type_name,
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+};
+
+/// Position of the voxel hosting a block entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Shrinkwrap)]
+pub struct BlockPosition(pub VoxelPos);
+
+/// An event describing a voxel that was replaced,
+/// see [`VoxelWorld::track_changes`](VoxelWorld::track_changes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockChange<T: Unit> {
+    pub pos: VoxelPos,
+    pub before: T,
+    pub after: T,
+}
+
+impl<T: Unit> BlockChange<T> {
+    /// Every voxel change of an edit, ready to be written to an `EventChannel`.
+    pub fn from_edit<A: Accessor>(edit: &WorldEdit<T>) -> Vec<Self> {
+        edit.changes::<A>()
+            .map(|(pos, before, after)| Self { pos, before, after })
+            .collect()
+    }
+}
+
+type PlaceFn = Box<dyn Fn(&mut CommandBuffer, Entity, VoxelPos) + Send + Sync>;
+type SaveFn = Box<dyn Fn(&World, Entity) -> Option<Vec<u8>> + Send + Sync>;
+type LoadFn = Box<dyn Fn(&mut World, Entity, &[u8]) -> Result<()> + Send + Sync>;
+
+/// Describes the block entity of a kind of block.
+pub struct BlockEntityKind {
+    on_place: PlaceFn,
+    on_remove: Option<PlaceFn>,
+    save: Option<SaveFn>,
+    load: Option<LoadFn>,
+}
+
+impl BlockEntityKind {
+    /// `on_place` is called with the freshly spawned entity,
+    /// and should add its payload components.
+    pub fn new<F>(on_place: F) -> Self
+    where
+        F: Fn(&mut CommandBuffer, Entity, VoxelPos) + Send + Sync + 'static,
+    {
+        Self {
+            on_place: Box::new(on_place),
+            on_remove: None,
+            save: None,
+            load: None,
+        }
+    }
+
+    /// Called right before the entity of a removed block is despawned,
+    /// e.g. to drop the content of a chest.
+    pub fn on_remove<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&mut CommandBuffer, Entity, VoxelPos) + Send + Sync + 'static,
+    {
+        self.on_remove = Some(Box::new(f));
+        self
+    }
+
+    /// Makes the payload persistent with chunk saves.
+    pub fn persist<S, L>(mut self, save: S, load: L) -> Self
+    where
+        S: Fn(&World, Entity) -> Option<Vec<u8>> + Send + Sync + 'static,
+        L: Fn(&mut World, Entity, &[u8]) -> Result<()> + Send + Sync + 'static,
+    {
+        self.save = Some(Box::new(save));
+        self.load = Some(Box::new(load));
+        self
+    }
+}
+
+/// A resource of every block value that hosts a block entity.
+pub struct BlockEntityHooks<T: Unit> {
+    kinds: HashMap<T, BlockEntityKind>,
+}
+
+impl<T: Unit> Default for BlockEntityHooks<T> {
+    fn default() -> Self {
+        Self {
+            kinds: HashMap::default(),
+        }
+    }
+}
+
+impl<T: Unit> BlockEntityHooks<T> {
+    pub fn register(&mut self, block: T, kind: BlockEntityKind) {
+        if self.kinds.insert(block, kind).is_some() {
+            log::warn!("block entity of `{:?}` registered twice", block);
+        }
+    }
+
+    pub fn get(&self, block: T) -> Option<&BlockEntityKind> {
+        self.kinds.get(&block)
+    }
+}
+
+/// A resource mapping voxel positions to their block entity.
+#[derive(Debug)]
+pub struct BlockEntities<A: Accessor, T: Unit> {
+    map: HashMap<VoxelPos, (Entity, T)>,
+    by_chunk: HashMap<ChunkCoord, HashSet<VoxelPos>>,
+    state: PhantomData<A>,
+}
+
+impl<A: Accessor, T: Unit> Default for BlockEntities<A, T> {
+    fn default() -> Self {
+        Self {
+            map: HashMap::default(),
+            by_chunk: HashMap::default(),
+            state: PhantomData::default(),
+        }
+    }
+}
+
+impl<A: Accessor, T: Unit> BlockEntities<A, T> {
+    pub fn get(&self, pos: VoxelPos) -> Option<Entity> {
+        self.map.get(&pos).map(|&(e, _)| e)
+    }
+
+    /// Block entities hosted by a chunk, with their block.
+    pub fn in_chunk(&self, coord: ChunkCoord) -> impl Iterator<Item = (VoxelPos, Entity, T)> + '_ {
+        self.by_chunk
+            .get(&coord)
+            .into_iter()
+            .flatten()
+            .filter_map(move |pos| self.map.get(pos).map(|&(e, t)| (*pos, e, t)))
+    }
+
+    /// Despawns the block entities of an unloaded chunk,
+    /// without calling their `on_remove` hook. Returns how many were despawned.
+    pub fn unload_chunk(&mut self, world: &mut World, coord: ChunkCoord) -> usize {
+        let positions = self.by_chunk.remove(&coord).unwrap_or_default();
+
+        positions
+            .iter()
+            .filter_map(|pos| self.map.remove(pos))
+            .filter(|&(e, _)| world.remove(e))
+            .count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Maps a position to its entity, returning the one it replaced.
+    fn insert(&mut self, pos: VoxelPos, entity: Entity, block: T) -> Option<(Entity, T)> {
+        let (coord, _) = split_pos::<A>(pos);
+        self.by_chunk.entry(coord).or_default().insert(pos);
+
+        self.map.insert(pos, (entity, block))
+    }
+
+    fn remove(&mut self, pos: VoxelPos) -> Option<(Entity, T)> {
+        let removed = self.map.remove(&pos)?;
+        let (coord, _) = split_pos::<A>(pos);
+
+        if let Some(positions) = self.by_chunk.get_mut(&coord) {
+            positions.remove(&pos);
+
+            if positions.is_empty() {
+                self.by_chunk.remove(&coord);
+            }
+        }
+
+        Some(removed)
+    }
+}
+
+impl<A: Accessor, T: UnitBytes> BlockEntities<A, T> {
+    /// Encodes the persistent block entities of a chunk,
+    /// meant to be stored next to the chunk's voxels,
+    /// see [`WorldSave`](super::region::WorldSave).
+    pub fn save_chunk(
+        &self,
+        world: &World,
+        hooks: &BlockEntityHooks<T>,
+        coord: ChunkCoord,
+    ) -> Vec<u8> {
+        let saved: Vec<(usize, T, Vec<u8>)> = self
+            .in_chunk(coord)
+            .filter_map(|(pos, entity, block)| {
+                let payload = (hooks.get(block)?.save.as_ref()?)(world, entity)?;
+                Some((A::to_index(split_pos::<A>(pos).1), block, payload))
+            })
+            .collect();
+
+        let mut out = Vec::new();
+        write_varint(&mut out, saved.len() as u64);
+
+        for (index, block, payload) in saved {
+            write_varint(&mut out, index as u64);
+            block.write_bytes(&mut out);
+            write_varint(&mut out, payload.len() as u64);
+            out.extend_from_slice(&payload);
+        }
+
+        out
+    }
+
+    /// Respawns the block entities saved by [`save_chunk`](BlockEntities::save_chunk).
+    ///
+    /// Nothing is spawned if the bytes are malformed or a payload fails to load.
+    pub fn load_chunk(
+        &mut self,
+        world: &mut World,
+        hooks: &BlockEntityHooks<T>,
+        coord: ChunkCoord,
+        bytes: &[u8],
+    ) -> Result<()> {
+        let mut reader = ByteReader::new(bytes);
+        let len = reader.varint()?;
+        let mut saved = Vec::new();
+
+        for _ in 0..len {
+            let index = reader.varint()? as usize;
+            ensure!(
+                index < A::CUBE_LEN,
+                "block entity index {} out of chunk",
+                index
+            );
+
+            let block: T = reader.unit()?;
+            let payload_len = reader.varint()? as usize;
+            saved.push((index, block, reader.take(payload_len)?));
+        }
+
+        ensure!(reader.is_empty(), "trailing bytes after block entities");
+
+        let mut loaded = Vec::with_capacity(saved.len());
+        for (index, block, payload) in saved {
+            let load = match hooks.get(block).and_then(|k| k.load.as_ref()) {
+                Some(load) => load,
+                None => {
+                    log::warn!("no block entity loader for `{:?}`, skipped", block);
+                    continue;
+                }
+            };
+
+            let pos = join_pos::<A>(coord, A::from_index(index));
+            let entity = world.push((BlockPosition(pos),));
+            loaded.push((pos, entity, block));
+
+            if let Err(e) = load(world, entity, payload) {
+                for (_, entity, _) in loaded {
+                    world.remove(entity);
+                }
+
+                return Err(e);
+            }
+        }
+
+        for (pos, entity, block) in loaded {
+            if let Some((old, _)) = self.insert(pos, entity, block) {
+                world.remove(old);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns a `System` writing the voxel changes recorded by
+/// the `VoxelWorld` resource to an `EventChannel<BlockChange<T>>`.
+pub fn block_change_system<A: Accessor, T: Unit, const N: usize>() -> impl Runnable {
+    SystemBuilder::new(format!("BlockChange<{}>System", type_name::<T>()))
+        .write_resource::<VoxelWorld<A, T, N>>()
+        .write_resource::<EventChannel<BlockChange<T>>>()
+        .build(|_, _, (world, changes), _| {
+            changes.iter_write(world.take_changes());
+        })
+}
+
+/// Returns a `System` spawning and despawning block entities
+/// as their host blocks are placed and removed.
+pub fn block_entity_system<A: Accessor, T: Unit>(
+    reader_id: ReaderId<BlockChange<T>>,
+) -> impl Runnable {
+    let mut reader_id = reader_id;
+
+    SystemBuilder::new(format!("BlockEntity<{}>System", type_name::<T>()))
+        .read_resource::<EventChannel<BlockChange<T>>>()
+        .read_resource::<BlockEntityHooks<T>>()
+        .write_resource::<BlockEntities<A, T>>()
+        .build(move |cmd, _, (changes, hooks, entities), _| {
+            for change in changes.read(&mut reader_id) {
+                if let Some((entity, block)) = entities.remove(change.pos) {
+                    if let Some(on_remove) = hooks.get(block).and_then(|k| k.on_remove.as_ref()) {
+                        on_remove(cmd, entity, change.pos);
+                    }
+
+                    cmd.remove(entity);
+                }
+
+                if let Some(kind) = hooks.get(change.after) {
+                    let entity = cmd.push((BlockPosition(change.pos),));
+                    (kind.on_place)(cmd, entity, change.pos);
+
+                    entities.insert(change.pos, entity, change.after);
+                }
+            }
+        })
+}
+
+/// Inserts `BlockEntityHooks`, `BlockEntities` and a `BlockChange` channel,
+/// makes the `VoxelWorld` track its changes,
+/// then adds the [`block_change_system`](block_change_system)
+/// and the [`block_entity_system`](block_entity_system).
+pub fn block_entity_routine<A: Accessor, T: Unit, const N: usize>(
+    _: &mut World,
+    r: &mut Resources,
+    b: &mut Builder,
+) {
+    insert_if_none(r, VoxelWorld::<A, T, N>::default());
+    insert_if_none(r, BlockEntityHooks::<T>::default());
+    insert_if_none(r, BlockEntities::<A, T>::default());
+
+    r.get_mut::<VoxelWorld<A, T, N>>().unwrap().track_changes();
+
+    if !r.contains::<EventChannel<BlockChange<T>>>() {
+        new_channel::<BlockChange<T>>(r);
+    }
+
+    let id: ReaderId<BlockChange<T>> = subscribe(r);
+    b.add_system(block_change_system::<A, T, N>());
+    b.add_system(block_entity_system::<A, T>(id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use anyhow::anyhow;
+    use cgmath::Point3;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Counter(u8);
+
+    const CHEST: u8 = 1;
+
+    fn hooks() -> BlockEntityHooks<u8> {
+        let kind = BlockEntityKind::new(|cmd, e, _| cmd.add_component(e, Counter(0))).persist(
+            |world, e| {
+                let counter = *world.entry_ref(e).ok()?.get_component::<Counter>().ok()?;
+                Some(vec![counter.0])
+            },
+            |world, e, bytes| match bytes {
+                &[count] => {
+                    world.entry(e).unwrap().add_component(Counter(count));
+                    Ok(())
+                }
+                _ => Err(anyhow!("malformed counter")),
+            },
+        );
+
+        let mut hooks = BlockEntityHooks::default();
+        hooks.register(CHEST, kind);
+        hooks
+    }
+
+    fn spawn(world: &mut World, entities: &mut BlockEntities<Dim, u8>, pos: VoxelPos, count: u8) {
+        let entity = world.push((BlockPosition(pos), Counter(count)));
+        entities.insert(pos, entity, CHEST);
+    }
+
+    #[test]
+    fn chunk_index() {
+        let mut world = World::default();
+        let mut entities = BlockEntities::<Dim, u8>::default();
+        spawn(&mut world, &mut entities, Point3::new(0, 0, 0), 1);
+        spawn(&mut world, &mut entities, Point3::new(3, 3, 3), 2);
+        spawn(&mut world, &mut entities, Point3::new(-1, 0, 0), 3);
+
+        let origin = Point3::new(0, 0, 0);
+        assert_eq!(entities.in_chunk(origin).count(), 2);
+        assert_eq!(entities.in_chunk(Point3::new(-1, 0, 0)).count(), 1);
+
+        assert_eq!(entities.unload_chunk(&mut world, origin), 2);
+        assert_eq!(entities.in_chunk(origin).count(), 0);
+        assert_eq!(entities.len(), 1);
+        assert_eq!(world.len(), 1);
+    }
+
+    #[test]
+    fn place_and_remove() {
+        let removed = Arc::new(AtomicUsize::new(0));
+        let counter = removed.clone();
+        let kind = BlockEntityKind::new(|cmd, e, _| cmd.add_component(e, Counter(0))).on_remove(
+            move |_, _, _| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            },
+        );
+
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut builder = Schedule::builder();
+        block_entity_routine::<Dim, u8, 64>(&mut world, &mut resources, &mut builder);
+        let mut schedule = builder.build();
+
+        resources
+            .get_mut::<BlockEntityHooks<u8>>()
+            .unwrap()
+            .register(CHEST, kind);
+        resources
+            .get_mut::<VoxelWorld<Dim, u8, 64>>()
+            .unwrap()
+            .insert_chunk(Point3::new(0, 0, 0), Chunk::default());
+
+        let pos = Point3::new(1, 2, 3);
+        let set = |resources: &mut Resources, block: u8| {
+            let mut voxels = resources.get_mut::<VoxelWorld<Dim, u8, 64>>().unwrap();
+            voxels.set(pos, block);
+        };
+
+        set(&mut resources, CHEST);
+        schedule.execute(&mut world, &mut resources);
+
+        let entity = {
+            let entities = resources.get::<BlockEntities<Dim, u8>>().unwrap();
+            entities.get(pos).unwrap()
+        };
+        {
+            let entry = world.entry_ref(entity).unwrap();
+            assert_eq!(
+                *entry.get_component::<BlockPosition>().unwrap(),
+                BlockPosition(pos)
+            );
+            assert_eq!(*entry.get_component::<Counter>().unwrap(), Counter(0));
+        }
+        assert_eq!(removed.load(Ordering::Relaxed), 0);
+
+        set(&mut resources, 0);
+        schedule.execute(&mut world, &mut resources);
+
+        assert_eq!(removed.load(Ordering::Relaxed), 1);
+        assert!(!world.contains(entity));
+        assert!(resources
+            .get::<BlockEntities<Dim, u8>>()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let hooks = hooks();
+        let coord = Point3::new(1, 0, 0);
+
+        let mut world = World::default();
+        let mut entities = BlockEntities::<Dim, u8>::default();
+        spawn(&mut world, &mut entities, Point3::new(4, 1, 2), 7);
+        spawn(&mut world, &mut entities, Point3::new(7, 3, 3), 9);
+        let bytes = entities.save_chunk(&world, &hooks, coord);
+
+        let mut world = World::default();
+        let mut entities = BlockEntities::<Dim, u8>::default();
+        entities
+            .load_chunk(&mut world, &hooks, coord, &bytes)
+            .unwrap();
+
+        let entity = entities.get(Point3::new(7, 3, 3)).unwrap();
+        let entry = world.entry(entity).unwrap();
+        assert_eq!(*entry.get_component::<Counter>().unwrap(), Counter(9));
+        assert_eq!(entities.in_chunk(coord).count(), 2);
+    }
+
+    #[test]
+    fn failed_load_spawns_nothing() {
+        let hooks = hooks();
+        let coord = Point3::new(0, 0, 0);
+
+        // A valid entity followed by a malformed payload
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, 2);
+        for (index, payload) in [(0, vec![1]), (5, vec![1, 2])].iter() {
+            write_varint(&mut bytes, *index);
+            bytes.push(CHEST);
+            write_varint(&mut bytes, payload.len() as u64);
+            bytes.extend_from_slice(payload);
+        }
+
+        let mut world = World::default();
+        let mut entities = BlockEntities::<Dim, u8>::default();
+        assert!(entities
+            .load_chunk(&mut world, &hooks, coord, &bytes)
+            .is_err());
+        assert!(entities.is_empty());
+        assert_eq!(world.len(), 0);
+
+        // Truncated bytes don't spawn anything either
+        let saved = {
+            let mut world = World::default();
+            let mut entities = BlockEntities::<Dim, u8>::default();
+            spawn(&mut world, &mut entities, Point3::new(0, 0, 0), 1);
+            entities.save_chunk(&world, &hooks, coord)
+        };
+
+        assert!(entities
+            .load_chunk(&mut world, &hooks, coord, &saved[..saved.len() - 1])
+            .is_err());
+        assert_eq!(world.len(), 0);
+    }
+}
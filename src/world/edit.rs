@@ -0,0 +1,655 @@
+use super::{join_pos, split_pos, ChunkCoord, VoxelPos, VoxelWorld};
+use crate::chunk::{delta::ChunkDelta, Accessor, Unit};
+use cgmath::{Point3, Vector3};
+use std::collections::{hash_map::Iter, HashMap, HashSet};
+
+/// A volume of voxels.
+pub trait Shape {
+    /// Inclusive bounding box as `(min, max)`.
+    fn bounds(&self) -> (VoxelPos, VoxelPos);
+
+    fn contains(&self, pos: VoxelPos) -> bool;
+}
+
+/// Every position inside a shape.
+pub fn voxels<S: Shape + ?Sized>(shape: &S) -> impl Iterator<Item = VoxelPos> + '_ {
+    let (min, max) = shape.bounds();
+
+    (min.y..=max.y)
+        .flat_map(move |y| {
+            (min.z..=max.z).flat_map(move |z| (min.x..=max.x).map(move |x| Point3::new(x, y, z)))
+        })
+        .filter(move |&p| shape.contains(p))
+}
+
+/// An axis aligned box, both corners included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cuboid {
+    pub min: VoxelPos,
+    pub max: VoxelPos,
+}
+
+impl Cuboid {
+    /// A box spanning two opposite corners, in any order.
+    pub fn new(a: VoxelPos, b: VoxelPos) -> Self {
+        Self {
+            min: Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn size(&self) -> Vector3<i32> {
+        self.max - self.min + Vector3::new(1, 1, 1)
+    }
+}
+
+impl Shape for Cuboid {
+    fn bounds(&self) -> (VoxelPos, VoxelPos) {
+        (self.min, self.max)
+    }
+
+    fn contains(&self, p: VoxelPos) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x)
+            && (self.min.y..=self.max.y).contains(&p.y)
+            && (self.min.z..=self.max.z).contains(&p.z)
+    }
+}
+
+/// A ball of voxels whose centers lie within `radius`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub center: VoxelPos,
+    pub radius: f32,
+}
+
+impl Shape for Sphere {
+    fn bounds(&self) -> (VoxelPos, VoxelPos) {
+        let r = self.radius.max(0.).floor() as i32;
+        let r = Vector3::new(r, r, r);
+        (self.center - r, self.center + r)
+    }
+
+    fn contains(&self, p: VoxelPos) -> bool {
+        let d = (p - self.center).cast::<f32>().unwrap();
+        d.x * d.x + d.y * d.y + d.z * d.z <= self.radius * self.radius
+    }
+}
+
+/// An upright cylinder standing on `base`, `height` voxels tall.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cylinder {
+    pub base: VoxelPos,
+    pub radius: f32,
+    pub height: u32,
+}
+
+impl Shape for Cylinder {
+    fn bounds(&self) -> (VoxelPos, VoxelPos) {
+        let r = self.radius.max(0.).floor() as i32;
+        let top = self.height.max(1) as i32 - 1;
+        (
+            self.base - Vector3::new(r, 0, r),
+            self.base + Vector3::new(r, top, r),
+        )
+    }
+
+    fn contains(&self, p: VoxelPos) -> bool {
+        let dx = (p.x - self.base.x) as f32;
+        let dz = (p.z - self.base.z) as f32;
+        let dy = p.y - self.base.y;
+
+        dy >= 0 && dy < self.height as i32 && dx * dx + dz * dz <= self.radius * self.radius
+    }
+}
+
+/// Clockwise rotation around the Y axis, seen from above.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl Rotation {
+    pub fn rotate(&self, v: Vector3<i32>) -> Vector3<i32> {
+        use Rotation::*;
+        match self {
+            None => v,
+            Cw90 => Vector3::new(-v.z, v.y, v.x),
+            Cw180 => Vector3::new(-v.x, v.y, -v.z),
+            Cw270 => Vector3::new(v.z, v.y, -v.x),
+        }
+    }
+
+    /// The rotation applied after this one.
+    pub fn then(&self, other: Self) -> Self {
+        Self::from_quarters(self.quarters() + other.quarters())
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self::from_quarters(4 - self.quarters())
+    }
+
+    /// No. of clockwise quarter turns.
+    pub fn quarters(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_quarters(n: u8) -> Self {
+        use Rotation::*;
+        match n % 4 {
+            0 => None,
+            1 => Cw90,
+            2 => Cw180,
+            _ => Cw270,
+        }
+    }
+}
+
+/// A copied region of voxels, stored relative to its minimum corner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clipboard<T: Unit> {
+    size: Vector3<i32>,
+    voxels: Vec<(Vector3<i32>, T)>,
+}
+
+impl<T: Unit> Clipboard<T> {
+    /// Dimensions of the copied bounding box.
+    pub fn size(&self) -> Vector3<i32> {
+        self.size
+    }
+
+    /// Voxels with their offset from the minimum corner.
+    pub fn voxels(&self) -> &[(Vector3<i32>, T)] {
+        &self.voxels
+    }
+
+    /// Changes every copied voxel, e.g. rotating block states before pasting.
+    pub fn map<F: FnMut(T) -> T>(&mut self, mut f: F) {
+        self.voxels.iter_mut().for_each(|(_, t)| *t = f(*t));
+    }
+
+    /// Keeps only the voxels that should be pasted, e.g. dropping air.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.voxels.retain(|(_, t)| f(t));
+    }
+
+    pub fn len(&self) -> usize {
+        self.voxels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+}
+
+/// Writes grouped by chunk, so that every chunk is visited
+/// and dirtied only once when the batch is applied.
+#[derive(Debug, Clone)]
+pub struct EditBatch<T: Unit> {
+    writes: HashMap<ChunkCoord, Vec<([usize; 3], T)>>,
+}
+
+impl<T: Unit> Default for EditBatch<T> {
+    fn default() -> Self {
+        Self {
+            writes: HashMap::default(),
+        }
+    }
+}
+
+impl<T: Unit> EditBatch<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a write, later writes to the same voxel win.
+    pub fn push<A: Accessor>(&mut self, pos: VoxelPos, value: T) {
+        let (coord, local) = split_pos::<A>(pos);
+        self.writes.entry(coord).or_default().push((local, value));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+}
+
+/// The outcome of an edit, holding one `ChunkDelta` per changed chunk.
+/// Can be reverted and re-applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorldEdit<T: Unit> {
+    deltas: HashMap<ChunkCoord, ChunkDelta<T>>,
+}
+
+impl<T: Unit> Default for WorldEdit<T> {
+    fn default() -> Self {
+        Self {
+            deltas: HashMap::default(),
+        }
+    }
+}
+
+impl<T: Unit> WorldEdit<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every chunk that was changed by this edit.
+    pub fn changed_chunks(&self) -> HashSet<ChunkCoord> {
+        self.deltas.keys().copied().collect()
+    }
+
+    pub fn deltas(&self) -> Iter<'_, ChunkCoord, ChunkDelta<T>> {
+        self.deltas.iter()
+    }
+
+    pub fn delta(&self, coord: ChunkCoord) -> Option<&ChunkDelta<T>> {
+        self.deltas.get(&coord)
+    }
+
+    /// Merges in a delta applied after the ones already stored.
+    pub fn push_delta(&mut self, coord: ChunkCoord, delta: ChunkDelta<T>) {
+        let merged = self.deltas.entry(coord).or_default();
+        merged.append(&delta);
+
+        if merged.is_empty() {
+            self.deltas.remove(&coord);
+        }
+    }
+
+    /// Merges in an edit applied after this one.
+    pub fn append(&mut self, next: WorldEdit<T>) {
+        next.deltas
+            .into_iter()
+            .for_each(|(coord, delta)| self.push_delta(coord, delta));
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            deltas: self
+                .deltas
+                .iter()
+                .map(|(&coord, delta)| (coord, delta.inverse()))
+                .collect(),
+        }
+    }
+
+    /// Every changed voxel as `(position, before, after)`.
+    pub fn changes<A: Accessor>(&self) -> impl Iterator<Item = (VoxelPos, T, T)> + '_ {
+        self.deltas.iter().flat_map(|(&coord, delta)| {
+            delta.iter().map(move |c| {
+                (
+                    join_pos::<A>(coord, A::from_index(c.index)),
+                    c.before,
+                    c.after,
+                )
+            })
+        })
+    }
+
+    /// No. of voxels changed.
+    pub fn len(&self) -> usize {
+        self.deltas.values().map(ChunkDelta::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Re-applies this edit, skipping chunks that aren't loaded.
+    pub fn apply<A: Accessor, const N: usize>(&self, world: &mut VoxelWorld<A, T, N>) {
+        for (&coord, delta) in self.deltas.iter() {
+            if let Some(chunk) = world.chunk_mut(coord) {
+                delta.apply(chunk);
+                world.record_delta(coord, delta, false);
+            }
+        }
+    }
+
+    /// Undoes this edit, skipping chunks that aren't loaded.
+    pub fn revert<A: Accessor, const N: usize>(&self, world: &mut VoxelWorld<A, T, N>) {
+        for (&coord, delta) in self.deltas.iter() {
+            if let Some(chunk) = world.chunk_mut(coord) {
+                delta.revert(chunk);
+                world.record_delta(coord, delta, true);
+            }
+        }
+    }
+}
+
+impl<A: Accessor, T: Unit, const N: usize> VoxelWorld<A, T, N> {
+    /// Applies a batch of writes, voxels in unloaded chunks are skipped.
+    pub fn edit(&mut self, batch: EditBatch<T>) -> WorldEdit<T> {
+        let mut edit = WorldEdit::new();
+
+        for (coord, writes) in batch.writes {
+            if let Some(chunk) = self.chunks.get_mut(&coord) {
+                let mut delta = ChunkDelta::new();
+
+                for (local, value) in writes {
+                    delta.set(chunk, local, value);
+                }
+
+                if !delta.is_empty() {
+                    self.dirty.insert(coord);
+                    self.record_delta(coord, &delta, false);
+                    edit.deltas.insert(coord, delta);
+                }
+            }
+        }
+
+        edit
+    }
+
+    /// Records the voxel changes of a delta applied or reverted in a chunk.
+    fn record_delta(&mut self, coord: ChunkCoord, delta: &ChunkDelta<T>, reverted: bool) {
+        for c in delta.iter() {
+            let pos = join_pos::<A>(coord, A::from_index(c.index));
+
+            if reverted {
+                self.record_change(pos, c.after, c.before);
+            } else {
+                self.record_change(pos, c.before, c.after);
+            }
+        }
+    }
+
+    /// Sets every voxel in `shape` to `value`.
+    pub fn fill<S: Shape + ?Sized>(&mut self, shape: &S, value: T) -> WorldEdit<T> {
+        let mut batch = EditBatch::new();
+        voxels(shape).for_each(|p| batch.push::<A>(p, value));
+        self.edit(batch)
+    }
+
+    /// Replaces every `from` voxel in `shape` with `to`.
+    pub fn replace<S: Shape + ?Sized>(&mut self, shape: &S, from: T, to: T) -> WorldEdit<T> {
+        self.replace_with(shape, |t| if t == from { Some(to) } else { None })
+    }
+
+    /// Replaces voxels in `shape` for which `f` returns a new value.
+    pub fn replace_with<S, F>(&mut self, shape: &S, mut f: F) -> WorldEdit<T>
+    where
+        S: Shape + ?Sized,
+        F: FnMut(T) -> Option<T>,
+    {
+        let mut batch = EditBatch::new();
+
+        voxels(shape)
+            .filter_map(|p| Some((p, f(self.get(p)?)?)))
+            .for_each(|(p, t)| batch.push::<A>(p, t));
+
+        self.edit(batch)
+    }
+
+    /// Sets the inside of `shape` to `value`, leaving a one voxel thick shell.
+    pub fn hollow<S: Shape + ?Sized>(&mut self, shape: &S, value: T) -> WorldEdit<T> {
+        const NEIGHBOURS: [Vector3<i32>; 6] = [
+            Vector3::new(1, 0, 0),
+            Vector3::new(-1, 0, 0),
+            Vector3::new(0, 1, 0),
+            Vector3::new(0, -1, 0),
+            Vector3::new(0, 0, 1),
+            Vector3::new(0, 0, -1),
+        ];
+
+        let mut batch = EditBatch::new();
+
+        voxels(shape)
+            .filter(|&p| NEIGHBOURS.iter().all(|&n| shape.contains(p + n)))
+            .for_each(|p| batch.push::<A>(p, value));
+
+        self.edit(batch)
+    }
+
+    /// Copies the loaded voxels in `shape`.
+    pub fn copy<S: Shape + ?Sized>(&self, shape: &S) -> Clipboard<T> {
+        let (min, max) = shape.bounds();
+
+        Clipboard {
+            size: max - min + Vector3::new(1, 1, 1),
+            voxels: voxels(shape)
+                .filter_map(|p| Some((p - min, self.get(p)?)))
+                .collect(),
+        }
+    }
+
+    /// Pastes a clipboard rotated around the Y axis,
+    /// with the minimum corner of the rotated box at `origin`.
+    pub fn paste(
+        &mut self,
+        clip: &Clipboard<T>,
+        origin: VoxelPos,
+        rotation: Rotation,
+    ) -> WorldEdit<T> {
+        let mut batch = EditBatch::new();
+
+        // Rotating moves the far corner to negative X/Z, shift it back
+        let far = rotation.rotate(clip.size - Vector3::new(1, 1, 1));
+        let shift = Vector3::new((-far.x).max(0), 0, (-far.z).max(0));
+
+        clip.voxels
+            .iter()
+            .for_each(|&(offset, t)| batch.push::<A>(origin + shift + rotation.rotate(offset), t));
+
+        self.edit(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    type World = VoxelWorld<Dim, u8, 64>;
+
+    fn world() -> World {
+        let mut world = World::new();
+        for x in -2..2 {
+            for z in -2..2 {
+                world.insert_chunk(Point3::new(x, 0, z), Chunk::default());
+            }
+        }
+
+        world
+    }
+
+    #[test]
+    fn rotated_paste_starts_at_origin() {
+        let mut world = world();
+        world.set(Point3::new(0, 0, 0), 1);
+        world.set(Point3::new(2, 0, 0), 2);
+        world.set(Point3::new(0, 0, 1), 3);
+
+        let clip = world.copy(&Cuboid::new(Point3::new(0, 0, 0), Point3::new(2, 0, 1)));
+        let origin = Point3::new(-4, 1, -4);
+
+        for &rotation in [
+            Rotation::None,
+            Rotation::Cw90,
+            Rotation::Cw180,
+            Rotation::Cw270,
+        ]
+        .iter()
+        {
+            let edit = world.paste(&clip, origin, rotation);
+            let changed: Vec<_> = edit.changes::<Dim>().map(|(p, _, _)| p).collect();
+
+            let min_x = changed.iter().map(|p| p.x).min().unwrap();
+            let min_z = changed.iter().map(|p| p.z).min().unwrap();
+            assert_eq!((min_x, min_z), (origin.x, origin.z), "{:?}", rotation);
+
+            edit.revert(&mut world);
+        }
+    }
+
+    #[test]
+    fn paste_cw90_layout() {
+        let mut world = world();
+        world.set(Point3::new(0, 0, 0), 1);
+        world.set(Point3::new(2, 0, 0), 2);
+
+        let clip = world.copy(&Cuboid::new(Point3::new(0, 0, 0), Point3::new(2, 0, 0)));
+        world.paste(&clip, Point3::new(-4, 1, -4), Rotation::Cw90);
+
+        assert_eq!(world.get(Point3::new(-4, 1, -4)), Some(1));
+        assert_eq!(world.get(Point3::new(-4, 1, -2)), Some(2));
+    }
+
+    #[test]
+    fn tracked_changes() {
+        use crate::world::block_entity::BlockChange;
+
+        let change = |x, before, after| BlockChange {
+            pos: Point3::new(x, 0, 0),
+            before,
+            after,
+        };
+
+        let mut world = world();
+        world.set(Point3::new(0, 0, 0), 1);
+        assert!(world.take_changes().is_empty());
+
+        world.track_changes();
+        world.set(Point3::new(0, 0, 0), 2);
+        world.set(Point3::new(0, 0, 0), 2);
+        assert_eq!(world.take_changes(), vec![change(0, 1, 2)]);
+
+        let edit = world.fill(&Cuboid::new(Point3::new(1, 0, 0), Point3::new(1, 0, 0)), 3);
+        assert_eq!(world.take_changes(), vec![change(1, 0, 3)]);
+
+        edit.revert(&mut world);
+        assert_eq!(world.take_changes(), vec![change(1, 3, 0)]);
+        edit.apply(&mut world);
+        assert_eq!(world.take_changes(), vec![change(1, 0, 3)]);
+        assert!(world.take_changes().is_empty());
+    }
+
+    fn chunks(coords: &[(i32, i32)]) -> HashSet<ChunkCoord> {
+        coords.iter().map(|&(x, z)| Point3::new(x, 0, z)).collect()
+    }
+
+    #[test]
+    fn fill_cuboid() {
+        let mut world = world();
+
+        let edit = world.fill(&Cuboid::new(Point3::new(1, 1, 0), Point3::new(-1, 0, 0)), 1);
+        assert_eq!(edit.len(), 6);
+        assert_eq!(edit.changed_chunks(), chunks(&[(-1, 0), (0, 0)]));
+        assert_eq!(world.get(Point3::new(-1, 1, 0)), Some(1));
+        assert_eq!(world.get(Point3::new(2, 0, 0)), Some(0));
+
+        // Nothing changes when filling again
+        let edit = world.fill(&Cuboid::new(Point3::new(1, 1, 0), Point3::new(-1, 0, 0)), 1);
+        assert!(edit.is_empty());
+        assert!(edit.changed_chunks().is_empty());
+
+        // Voxels of unloaded chunks are skipped
+        let edit = world.fill(&Cuboid::new(Point3::new(6, 0, 0), Point3::new(9, 0, 0)), 1);
+        assert_eq!(edit.len(), 2);
+        assert_eq!(edit.changed_chunks(), chunks(&[(1, 0)]));
+    }
+
+    #[test]
+    fn fill_sphere() {
+        let mut world = world();
+        let sphere = Sphere {
+            center: Point3::new(0, 1, 0),
+            radius: 1.5,
+        };
+
+        // A 3x3x3 cube without its corners
+        let edit = world.fill(&sphere, 1);
+        assert_eq!(edit.len(), 19);
+        assert_eq!(
+            edit.changed_chunks(),
+            chunks(&[(-1, -1), (-1, 0), (0, -1), (0, 0)])
+        );
+        assert_eq!(world.get(Point3::new(-1, 1, -1)), Some(1));
+        assert_eq!(world.get(Point3::new(-1, 0, -1)), Some(0));
+    }
+
+    #[test]
+    fn fill_cylinder() {
+        let mut world = world();
+        let cylinder = Cylinder {
+            base: Point3::new(0, 0, 2),
+            radius: 1.,
+            height: 3,
+        };
+
+        let edit = world.fill(&cylinder, 1);
+        assert_eq!(edit.len(), 15);
+        assert_eq!(edit.changed_chunks(), chunks(&[(-1, 0), (0, 0)]));
+        assert_eq!(world.get(Point3::new(-1, 2, 2)), Some(1));
+        assert_eq!(world.get(Point3::new(0, 3, 2)), Some(0));
+        assert_eq!(world.get(Point3::new(1, 0, 3)), Some(0));
+    }
+
+    #[test]
+    fn replace() {
+        let mut world = world();
+        world.fill(&Cuboid::new(Point3::new(2, 0, 0), Point3::new(5, 0, 0)), 1);
+        world.set(Point3::new(3, 0, 0), 2);
+
+        let edit = world.replace(
+            &Cuboid::new(Point3::new(-8, 0, 0), Point3::new(7, 0, 0)),
+            1,
+            3,
+        );
+        assert_eq!(edit.len(), 3);
+        assert_eq!(edit.changed_chunks(), chunks(&[(0, 0), (1, 0)]));
+        assert_eq!(world.get(Point3::new(2, 0, 0)), Some(3));
+        assert_eq!(world.get(Point3::new(3, 0, 0)), Some(2));
+        assert_eq!(world.get(Point3::new(6, 0, 0)), Some(0));
+    }
+
+    #[test]
+    fn hollow() {
+        let mut world = world();
+
+        let edit = world.hollow(
+            &Cuboid::new(Point3::new(-2, 0, -2), Point3::new(2, 3, 2)),
+            1,
+        );
+        assert_eq!(edit.len(), 3 * 2 * 3);
+        assert_eq!(
+            edit.changed_chunks(),
+            chunks(&[(-1, -1), (-1, 0), (0, -1), (0, 0)])
+        );
+        assert_eq!(world.get(Point3::new(0, 1, 0)), Some(1));
+        assert_eq!(world.get(Point3::new(0, 0, 0)), Some(0));
+        assert_eq!(world.get(Point3::new(-2, 1, 0)), Some(0));
+    }
+
+    #[test]
+    fn copy() {
+        let mut world = world();
+        world.set(Point3::new(-1, 0, 0), 1);
+        world.set(Point3::new(1, 0, 1), 2);
+
+        let clip = world.copy(&Cuboid::new(Point3::new(-1, 0, 0), Point3::new(1, 0, 1)));
+        assert_eq!(clip.size(), Vector3::new(3, 1, 2));
+        assert_eq!(clip.len(), 6);
+        assert!(clip.voxels().contains(&(Vector3::new(0, 0, 0), 1)));
+        assert!(clip.voxels().contains(&(Vector3::new(2, 0, 1), 2)));
+
+        // Voxels of unloaded chunks are left out
+        let clip = world.copy(&Cuboid::new(Point3::new(7, 0, 0), Point3::new(8, 0, 0)));
+        assert_eq!(clip.size(), Vector3::new(2, 1, 1));
+        assert_eq!(clip.len(), 1);
+    }
+}
@@ -0,0 +1,252 @@
+use super::{chunk_event_system, join_pos, ChunkCoord, ChunkEvent, VoxelWorld};
+use crate::{
+    chunk::{mesh::ChunkMesh, visibility::ChunkConnectivity, Accessor, Chunk, Unit},
+    core::{
+        ecs::{
+            systems::{Builder, Runnable},
+            *,
+        },
+        events::{new_channel, subscribe, EventChannel, ReaderId},
+    },
+    gfx::frustum::{Aabb, Bounds, Visible},
+};
+use shrinkwraprs::*;
+use std::{any::type_name, collections::HashMap};
+
+/// Position of a chunk entity, in units of chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Shrinkwrap)]
+pub struct ChunkPosition(pub ChunkCoord);
+
+/// Voxels of a chunk entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Shrinkwrap)]
+#[shrinkwrap(mutable)]
+pub struct ChunkData<A: Accessor, T: Unit, const N: usize>(pub Chunk<A, T, N>);
+
+impl ChunkPosition {
+    /// World space bounds of the chunk.
+    pub fn bounds<A: Accessor>(&self) -> Bounds {
+        let min = join_pos::<A>(self.0, [0, 0, 0]).cast::<f32>().unwrap();
+        Bounds(Aabb::cube(min, A::SIDE_LEN as f32))
+    }
+}
+
+/// A resource mapping chunk coordinates to their entity.
+#[derive(Debug, Default)]
+pub struct ChunkEntities {
+    map: HashMap<ChunkCoord, Entity>,
+}
+
+impl ChunkEntities {
+    pub fn get(&self, coord: ChunkCoord) -> Option<Entity> {
+        self.map.get(&coord).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ChunkCoord, Entity)> + '_ {
+        self.map.iter().map(|(&c, &e)| (c, e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+/// Returns a `System` spawning, updating and despawning chunk entities
+/// from the `ChunkEvent`s of the `VoxelWorld` resource.
+///
+/// Spawned entities have a `ChunkPosition`, a `ChunkData`,
+/// an empty `ChunkMesh`, its `ChunkConnectivity` and culling `Bounds`.
+pub fn chunk_entity_system<A: Accessor, T: Unit, const N: usize>(
+    reader_id: ReaderId<ChunkEvent>,
+) -> impl Runnable {
+    let mut reader_id = reader_id;
+
+    SystemBuilder::new(format!("ChunkEntity<{}>System", type_name::<T>()))
+        .read_resource::<VoxelWorld<A, T, N>>()
+        .read_resource::<EventChannel<ChunkEvent>>()
+        .write_resource::<ChunkEntities>()
+        .build(move |cmd, _, (world, events, entities), _| {
+            for event in events.read(&mut reader_id) {
+                match *event {
+                    ChunkEvent::Changed(coord) => {
+                        if let Some(&chunk) = world.chunk(coord) {
+                            match entities.get(coord) {
+                                Some(entity) => cmd.add_component(entity, ChunkData(chunk)),
+                                None => {
+                                    let pos = ChunkPosition(coord);
+                                    let entity = cmd.push((
+                                        pos,
+                                        pos.bounds::<A>(),
+                                        Visible::default(),
+                                        ChunkData(chunk),
+                                        ChunkMesh::default(),
+                                        ChunkConnectivity::default(),
+                                    ));
+
+                                    entities.map.insert(coord, entity);
+                                }
+                            }
+                        }
+                    }
+
+                    ChunkEvent::Removed(coord) => {
+                        if let Some(entity) = entities.map.remove(&coord) {
+                            cmd.remove(entity);
+                        }
+                    }
+                }
+            }
+        })
+}
+
+/// Returns a `System` rebuilding the `ChunkMesh` and `ChunkConnectivity`
+/// of every chunk entity whose `ChunkData` changed,
+/// meshing voxels for which `opaque` is true.
+pub fn chunk_mesh_system<A, T, F, const N: usize>(opaque: F) -> impl Runnable
+where
+    A: Accessor,
+    T: Unit,
+    F: Fn(&T) -> bool + Send + Sync + 'static,
+{
+    SystemBuilder::new(format!("ChunkMesh<{}>System", type_name::<T>()))
+        .with_query(
+            <(&ChunkData<A, T, N>, &mut ChunkMesh, &mut ChunkConnectivity)>::query()
+                .filter(maybe_changed::<ChunkData<A, T, N>>()),
+        )
+        .build(move |_, world, _, query| {
+            for (data, mesh, connectivity) in query.iter_mut(world) {
+                *mesh = ChunkMesh::build(&**data, &opaque);
+                *connectivity = ChunkConnectivity::compute(&**data, &opaque);
+            }
+        })
+}
+
+/// Inserts a `VoxelWorld`, a `ChunkEvent` channel and `ChunkEntities`,
+/// then adds the systems keeping chunk entities in sync with the world.
+pub fn chunk_entity_routine<A: Accessor, T: Unit, const N: usize>(
+    _: &mut World,
+    r: &mut Resources,
+    b: &mut Builder,
+) {
+    insert_if_none(r, VoxelWorld::<A, T, N>::default());
+    insert_if_none(r, ChunkEntities::default());
+
+    if !r.contains::<EventChannel<ChunkEvent>>() {
+        new_channel::<ChunkEvent>(r);
+    }
+
+    let id: ReaderId<ChunkEvent> = subscribe(r);
+
+    b.add_system(chunk_event_system::<A, T, N>());
+    b.add_system(chunk_entity_system::<A, T, N>(id));
+    b.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    type Voxels = VoxelWorld<Dim, u8, 64>;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Pinned;
+
+    fn setup() -> (World, Resources, Schedule) {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut builder = Schedule::builder();
+
+        chunk_entity_routine::<Dim, u8, 64>(&mut world, &mut resources, &mut builder);
+        builder.add_system(chunk_mesh_system::<Dim, u8, _, 64>(|t: &u8| *t != 0));
+
+        (world, resources, builder.build())
+    }
+
+    fn entity(resources: &Resources, coord: ChunkCoord) -> Option<Entity> {
+        resources.get::<ChunkEntities>().unwrap().get(coord)
+    }
+
+    fn faces(world: &World, entity: Entity) -> usize {
+        let entry = world.entry_ref(entity).unwrap();
+        entry.get_component::<ChunkMesh>().unwrap().face_count()
+    }
+
+    #[test]
+    fn spawn_and_despawn() {
+        let (mut world, mut resources, mut schedule) = setup();
+        let coord = Point3::new(1, 0, -1);
+
+        resources
+            .get_mut::<Voxels>()
+            .unwrap()
+            .insert_chunk(coord, Chunk::default());
+        schedule.execute(&mut world, &mut resources);
+
+        let chunk = entity(&resources, coord).unwrap();
+        {
+            let entry = world.entry_ref(chunk).unwrap();
+            assert_eq!(
+                *entry.get_component::<ChunkPosition>().unwrap(),
+                ChunkPosition(coord)
+            );
+        }
+
+        resources.get_mut::<Voxels>().unwrap().remove_chunk(coord);
+        schedule.execute(&mut world, &mut resources);
+
+        assert!(!world.contains(chunk));
+        assert_eq!(entity(&resources, coord), None);
+        assert!(resources.get::<ChunkEntities>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn only_changed_chunks_are_meshed() {
+        let (mut world, mut resources, mut schedule) = setup();
+        let (a, b) = (Point3::new(0, 0, 0), Point3::new(1, 0, 0));
+
+        let mut chunk = Chunk::default();
+        chunk[[0, 0, 0]] = 1;
+        {
+            let mut voxels = resources.get_mut::<Voxels>().unwrap();
+            voxels.insert_chunk(a, chunk);
+            voxels.insert_chunk(b, chunk);
+        }
+        schedule.execute(&mut world, &mut resources);
+
+        let (a, b) = (
+            entity(&resources, a).unwrap(),
+            entity(&resources, b).unwrap(),
+        );
+        assert_eq!((faces(&world, a), faces(&world, b)), (6, 6));
+
+        // Changes are tracked per archetype, so `b` is moved out of `a`'s
+        world.entry(b).unwrap().add_component(Pinned);
+        schedule.execute(&mut world, &mut resources);
+
+        for &e in [a, b].iter() {
+            let mut entry = world.entry(e).unwrap();
+            *entry.get_component_mut::<ChunkMesh>().unwrap() = ChunkMesh::default();
+        }
+
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!((faces(&world, a), faces(&world, b)), (0, 0));
+
+        resources
+            .get_mut::<Voxels>()
+            .unwrap()
+            .set(Point3::new(1, 0, 0), 1);
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!((faces(&world, a), faces(&world, b)), (10, 0));
+    }
+}
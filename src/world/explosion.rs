@@ -0,0 +1,292 @@
+use super::{
+    edit::{EditBatch, WorldEdit},
+    VoxelPos, VoxelWorld,
+};
+use crate::chunk::{Accessor, Unit};
+use cgmath::{InnerSpace, Point3, Vector3};
+use std::collections::HashMap;
+
+/// Rays are cast through every voxel of the surface of a cube of this side.
+const RAY_GRID: i32 = 16;
+/// Length of a ray step, in voxels.
+const RAY_STEP: f32 = 0.3;
+/// Power lost by a ray per step, even through empty voxels.
+const STEP_DECAY: f32 = 0.225;
+
+/// An explosion, carving terrain by casting rays from its center.
+///
+/// Every ray starts with `power` and loses some of it on each step,
+/// more so through voxels with a high blast resistance,
+/// destroying the voxels it reaches while it still has power left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Explosion {
+    pub center: Point3<f32>,
+    pub power: f32,
+}
+
+impl Explosion {
+    pub fn new(center: Point3<f32>, power: f32) -> Self {
+        Self { center, power }
+    }
+
+    /// Computes the voxels destroyed by the explosion without modifying the world.
+    ///
+    /// `resistance` returns `None` for voxels that can't be destroyed and don't
+    /// stop rays, e.g. air. Rays stop at unloaded chunks.
+    pub fn preview<A, T, R, const N: usize>(
+        &self,
+        world: &VoxelWorld<A, T, N>,
+        resistance: R,
+    ) -> Blast<T>
+    where
+        A: Accessor,
+        T: Unit,
+        R: Fn(&T) -> Option<f32>,
+    {
+        let mut voxels = HashMap::new();
+
+        for dir in ray_directions() {
+            let mut pos = self.center;
+            let mut power = self.power;
+
+            while power > 0. {
+                let voxel = pos.map(|v| v.floor() as i32);
+
+                let value = match world.get(voxel) {
+                    Some(value) => value,
+                    None => break,
+                };
+
+                if let Some(res) = resistance(&value) {
+                    power -= (res.max(0.) + RAY_STEP) * RAY_STEP;
+
+                    if power > 0. {
+                        voxels.insert(voxel, value);
+                    }
+                }
+
+                pos += dir * RAY_STEP;
+                power -= STEP_DECAY;
+            }
+        }
+
+        let mut voxels: Vec<(VoxelPos, T)> = voxels.into_iter().collect();
+        voxels.sort_by_key(|(p, _)| (p.y, p.z, p.x));
+
+        Blast { voxels }
+    }
+}
+
+/// Directions towards every voxel of the surface of a cube.
+fn ray_directions() -> impl Iterator<Item = Vector3<f32>> {
+    let last = RAY_GRID - 1;
+    let coord = move |i: i32| i as f32 / last as f32 * 2. - 1.;
+
+    (0..RAY_GRID * RAY_GRID * RAY_GRID)
+        .map(move |i| {
+            (
+                i % RAY_GRID,
+                (i / RAY_GRID) % RAY_GRID,
+                i / (RAY_GRID * RAY_GRID),
+            )
+        })
+        .filter(move |&(x, y, z)| [x, y, z].iter().any(|&c| c == 0 || c == last))
+        .map(move |(x, y, z)| Vector3::new(coord(x), coord(y), coord(z)).normalize())
+}
+
+/// The voxels destroyed by an [`Explosion`](Explosion), with their values
+/// at the time of the preview, sorted from the bottom up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blast<T: Unit> {
+    voxels: Vec<(VoxelPos, T)>,
+}
+
+impl<T: Unit> Blast<T> {
+    pub fn voxels(&self) -> &[(VoxelPos, T)] {
+        &self.voxels
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = VoxelPos> + '_ {
+        self.voxels.iter().map(|&(p, _)| p)
+    }
+
+    /// Keeps the voxels for which `f` returns true,
+    /// e.g. to protect some area.
+    pub fn retain<F: FnMut(VoxelPos, T) -> bool>(&mut self, mut f: F) {
+        self.voxels.retain(|&(p, t)| f(p, t));
+    }
+
+    pub fn len(&self) -> usize {
+        self.voxels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+}
+
+impl<A: Accessor, T: Unit, const N: usize> VoxelWorld<A, T, N> {
+    /// Replaces the voxels of a blast with `air`, in a single batch.
+    ///
+    /// `drops` is called with every destroyed voxel and its value,
+    /// in the order of the blast's voxels. Voxels that were already `air` are skipped.
+    pub fn apply_blast<D>(&mut self, blast: &Blast<T>, air: T, mut drops: D) -> WorldEdit<T>
+    where
+        D: FnMut(VoxelPos, T),
+    {
+        let mut batch = EditBatch::new();
+        blast.positions().for_each(|p| batch.push::<A>(p, air));
+
+        let edit = self.edit(batch);
+        let destroyed: HashMap<VoxelPos, T> = edit
+            .changes::<A>()
+            .map(|(pos, before, _)| (pos, before))
+            .collect();
+
+        for pos in blast.positions() {
+            if let Some(&before) = destroyed.get(&pos) {
+                drops(pos, before);
+            }
+        }
+
+        edit
+    }
+
+    /// Previews then applies an explosion,
+    /// see [`Explosion::preview`](Explosion::preview) and
+    /// [`apply_blast`](VoxelWorld::apply_blast).
+    pub fn explode<R, D>(
+        &mut self,
+        explosion: &Explosion,
+        resistance: R,
+        air: T,
+        drops: D,
+    ) -> WorldEdit<T>
+    where
+        R: Fn(&T) -> Option<f32>,
+        D: FnMut(VoxelPos, T),
+    {
+        let blast = explosion.preview(self, resistance);
+        self.apply_blast(&blast, air, drops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        world::edit::{voxels, Cuboid},
+    };
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    type World = VoxelWorld<Dim, u8, 64>;
+
+    const AIR: u8 = 0;
+    const DIRT: u8 = 1;
+    const STONE: u8 = 2;
+
+    /// A 16 voxels wide cube of dirt, centered on the origin.
+    fn world() -> World {
+        let mut world = World::new();
+        for x in -2..2 {
+            for y in -2..2 {
+                for z in -2..2 {
+                    world.insert_chunk(Point3::new(x, y, z), Chunk::default());
+                }
+            }
+        }
+
+        world.fill(
+            &Cuboid::new(Point3::new(-8, -8, -8), Point3::new(7, 7, 7)),
+            DIRT,
+        );
+        world
+    }
+
+    fn resistance(t: &u8) -> Option<f32> {
+        match *t {
+            AIR => None,
+            DIRT => Some(0.),
+            _ => Some(4.),
+        }
+    }
+
+    fn explosion(power: f32) -> Explosion {
+        Explosion::new(Point3::new(0.5, 0.5, 0.5), power)
+    }
+
+    fn distance(pos: VoxelPos) -> f32 {
+        (pos.cast::<f32>().unwrap() + Vector3::new(0.5, 0.5, 0.5) - explosion(0.).center)
+            .magnitude()
+    }
+
+    #[test]
+    fn blast_radius() {
+        let world = world();
+        let blast = explosion(4.).preview(&world, resistance);
+
+        // Rays lose a bit more than a unit of power per voxel
+        assert!(blast.positions().all(|p| distance(p) <= 4.5));
+        let reached = |p: &VoxelPos| blast.positions().any(|b| b == *p);
+        let inner = Cuboid::new(Point3::new(-1, -1, -1), Point3::new(1, 1, 1));
+        assert!(voxels(&inner).all(|p| reached(&p)));
+
+        let larger = explosion(6.).preview(&world, resistance);
+        assert!(larger.len() > blast.len());
+        assert!(blast
+            .positions()
+            .all(|p| larger.positions().any(|l| l == p)));
+    }
+
+    #[test]
+    fn resistance_attenuates() {
+        let mut world = world();
+        let dirt = explosion(4.).preview(&world, resistance);
+
+        world.fill(
+            &Cuboid::new(Point3::new(-8, -8, -8), Point3::new(7, 7, 7)),
+            STONE,
+        );
+        let stone = explosion(4.).preview(&world, resistance);
+        assert!(stone.len() < dirt.len());
+
+        // Air is never destroyed
+        world.fill(
+            &Cuboid::new(Point3::new(-8, -8, -8), Point3::new(7, 7, 7)),
+            AIR,
+        );
+        assert!(explosion(4.).preview(&world, resistance).is_empty());
+    }
+
+    #[test]
+    fn drops_in_blast_order() {
+        let mut world = world();
+        world.set(Point3::new(0, 0, 0), AIR);
+        world.set(Point3::new(1, 0, 0), STONE);
+
+        let blast = explosion(4.).preview(&world, resistance);
+        let expected: Vec<(VoxelPos, u8)> = blast
+            .voxels()
+            .iter()
+            .copied()
+            .filter(|&(_, t)| t != AIR)
+            .collect();
+
+        let mut drops = Vec::new();
+        let edit = world.apply_blast(&blast, AIR, |p, t| drops.push((p, t)));
+
+        assert_eq!(drops, expected);
+        assert_eq!(edit.len(), expected.len());
+        assert!(drops.contains(&(Point3::new(1, 0, 0), STONE)));
+        assert!(drops
+            .windows(2)
+            .all(|w| (w[0].0.y, w[0].0.z, w[0].0.x) < (w[1].0.y, w[1].0.z, w[1].0.x)));
+    }
+}
@@ -0,0 +1,182 @@
+use super::{ChunkCoord, VoxelWorld};
+use crate::{
+    chunk::{Accessor, Chunk, Unit},
+    core::ecs::{
+        systems::{Builder, Runnable},
+        *,
+    },
+    jobs::{Cancel, JobPool, Jobs, DEFAULT_MAX_IN_FLIGHT},
+};
+use cgmath::{Point3, Vector3};
+use std::{any::type_name, sync::Arc};
+
+/// Jobs generating chunks in the background.
+pub type ChunkJobs<A, T, const N: usize> = Jobs<ChunkCoord, Chunk<A, T, N>>;
+
+type GenerateFn<A, T, const N: usize> =
+    Arc<dyn Fn(ChunkCoord, &Cancel) -> Chunk<A, T, N> + Send + Sync>;
+
+/// Squared distance between two chunks, saturating at `u64::MAX`.
+pub fn distance_squared(a: ChunkCoord, b: ChunkCoord) -> u64 {
+    let axis = |a: i32, b: i32| {
+        let d = (a as i64 - b as i64).unsigned_abs();
+        d * d
+    };
+
+    axis(a.x, b.x)
+        .saturating_add(axis(a.y, b.y))
+        .saturating_add(axis(a.z, b.z))
+}
+
+/// Priority of a chunk job, its squared distance to `center`
+/// saturating at `u32::MAX`, so that closer chunks are generated first.
+pub fn distance_priority(center: ChunkCoord, coord: ChunkCoord) -> u32 {
+    distance_squared(center, coord).min(u32::MAX as u64) as u32
+}
+
+/// A resource deciding which chunks are generated,
+/// every chunk within `radius` of `center`.
+pub struct ChunkLoader<A: Accessor, T: Unit, const N: usize> {
+    pub center: ChunkCoord,
+    pub radius: u32,
+    generate: GenerateFn<A, T, N>,
+    last: Option<(ChunkCoord, u32)>,
+}
+
+impl<A: Accessor, T: Unit, const N: usize> ChunkLoader<A, T, N> {
+    /// `generate` runs on the `JobPool`, and should return early
+    /// with any chunk once cancelled.
+    pub fn new<F>(radius: u32, generate: F) -> Self
+    where
+        F: Fn(ChunkCoord, &Cancel) -> Chunk<A, T, N> + Send + Sync + 'static,
+    {
+        Self {
+            center: Point3::new(0, 0, 0),
+            radius,
+            generate: Arc::new(generate),
+            last: None,
+        }
+    }
+
+    pub fn in_radius(&self, coord: ChunkCoord) -> bool {
+        distance_squared(self.center, coord) <= self.radius as u64 * self.radius as u64
+    }
+
+    /// Queues the generation of a chunk, closer chunks first.
+    fn spawn(&self, jobs: &mut ChunkJobs<A, T, N>, coord: ChunkCoord) {
+        let generate = self.generate.clone();
+        jobs.spawn(
+            coord,
+            distance_priority(self.center, coord),
+            move |cancel| generate(coord, cancel),
+        );
+    }
+}
+
+/// Returns a `System` queuing the generation of the chunks
+/// around the `ChunkLoader`'s center, and inserting the generated chunks
+/// into the `VoxelWorld`.
+///
+/// Jobs of chunks that left the radius are cancelled,
+/// failed jobs are queued again.
+pub fn chunk_generation_system<A, T, const N: usize>() -> impl Runnable
+where
+    A: Accessor,
+    T: Unit,
+{
+    SystemBuilder::new(format!("ChunkGeneration<{}>System", type_name::<T>()))
+        .write_resource::<ChunkLoader<A, T, N>>()
+        .write_resource::<ChunkJobs<A, T, N>>()
+        .write_resource::<VoxelWorld<A, T, N>>()
+        .build(|_, _, (loader, jobs, world), _| {
+            if loader.last != Some((loader.center, loader.radius)) {
+                loader.last = Some((loader.center, loader.radius));
+
+                let center = loader.center;
+                jobs.retain(|&coord| loader.in_radius(coord));
+                jobs.reprioritize(|&coord| distance_priority(center, coord));
+
+                let r = loader.radius as i32;
+                for x in -r..=r {
+                    for y in -r..=r {
+                        for z in -r..=r {
+                            let coord = center + Vector3::new(x, y, z);
+
+                            if !loader.in_radius(coord)
+                                || world.contains_chunk(coord)
+                                || jobs.contains(&coord)
+                            {
+                                continue;
+                            }
+
+                            loader.spawn(jobs, coord);
+                        }
+                    }
+                }
+            }
+
+            for (coord, chunk) in jobs.poll() {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        log::error!("generation of chunk {:?} failed: {}", coord, e);
+                        if loader.in_radius(coord) && !world.contains_chunk(coord) {
+                            loader.spawn(jobs, coord);
+                        }
+                        continue;
+                    }
+                };
+
+                if loader.in_radius(coord) && !world.contains_chunk(coord) {
+                    world.insert_chunk(coord, chunk);
+                }
+            }
+        })
+}
+
+/// Inserts a `ChunkLoader` generating chunks with `generate`,
+/// a `JobPool` if there's none and `ChunkJobs`,
+/// then adds the [`chunk_generation_system`](chunk_generation_system).
+///
+/// Meant to be used with `routine_fn`, e.g.
+/// `routine_fn(chunk_generation_routine::<A, T, _, N>(8, generate))`.
+pub fn chunk_generation_routine<A, T, F, const N: usize>(
+    radius: u32,
+    generate: F,
+) -> impl FnMut(&mut World, &mut Resources, &mut Builder)
+where
+    A: Accessor,
+    T: Unit,
+    F: Fn(ChunkCoord, &Cancel) -> Chunk<A, T, N> + Send + Sync + Clone + 'static,
+{
+    move |_, r, b| {
+        insert_if_none(r, VoxelWorld::<A, T, N>::new());
+        insert_if_none(r, ChunkLoader::new(radius, generate.clone()));
+        insert_if_none(r, JobPool::default());
+
+        let jobs = ChunkJobs::<A, T, N>::new(&get_expect::<JobPool>(r), DEFAULT_MAX_IN_FLIGHT);
+        insert_if_none(r, jobs);
+
+        b.add_system(chunk_generation_system::<A, T, N>());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn far_chunks_saturate() {
+        let origin = Point3::new(0, 0, 0);
+
+        assert_eq!(distance_priority(origin, Point3::new(1, -2, 3)), 14);
+        assert_eq!(
+            distance_squared(Point3::new(i32::MIN, 0, 0), Point3::new(i32::MAX, 0, 0)),
+            (u32::MAX as u64).pow(2)
+        );
+        assert_eq!(
+            distance_priority(origin, Point3::new(i32::MIN, i32::MIN, i32::MIN)),
+            u32::MAX
+        );
+    }
+}
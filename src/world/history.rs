@@ -0,0 +1,348 @@
+use super::{edit::WorldEdit, ChunkCoord, VoxelWorld};
+use crate::{
+    chunk::{delta::VoxelChange, Accessor, Unit},
+    core::{
+        ecs::{
+            systems::{Builder, Runnable},
+            *,
+        },
+        events::{new_channel, subscribe, EventChannel, ReaderId},
+    },
+};
+use std::{
+    any::type_name,
+    collections::{HashSet, VecDeque},
+    mem::size_of,
+};
+
+pub const DEFAULT_HISTORY_DEPTH: usize = 128;
+pub const DEFAULT_HISTORY_MEMORY: usize = 64 * 1024 * 1024;
+
+/// An event requesting to move through an `EditHistory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EditCommand {
+    Undo,
+    Redo,
+}
+
+/// A resource recording reversible world edits,
+/// bounded by both a number of entries and an estimated memory usage.
+#[derive(Debug)]
+pub struct EditHistory<T: Unit> {
+    undo: VecDeque<(WorldEdit<T>, usize)>,
+    redo: Vec<(WorldEdit<T>, usize)>,
+    transaction: Option<(WorldEdit<T>, usize)>,
+    depth: usize,
+    memory_cap: usize,
+    memory: usize,
+}
+
+impl<T: Unit> Default for EditHistory<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_DEPTH, DEFAULT_HISTORY_MEMORY)
+    }
+}
+
+impl<T: Unit> EditHistory<T> {
+    /// A history keeping at most `depth` entries
+    /// and roughly `memory_cap` bytes of changes.
+    pub fn new(depth: usize, memory_cap: usize) -> Self {
+        Self {
+            undo: VecDeque::with_capacity(depth.min(DEFAULT_HISTORY_DEPTH)),
+            redo: Vec::new(),
+            transaction: None,
+            depth,
+            memory_cap,
+            memory: 0,
+        }
+    }
+
+    /// Records an edit that was already applied to the world.
+    /// Inside a transaction, the edit is merged into it instead.
+    pub fn record(&mut self, edit: WorldEdit<T>) {
+        if edit.is_empty() {
+            return;
+        }
+
+        if let Some((transaction, _)) = &mut self.transaction {
+            transaction.append(edit);
+            return;
+        }
+
+        self.redo.clear();
+        self.push_undo(edit);
+    }
+
+    /// Starts grouping every recorded edit into a single entry.
+    /// Transactions can be nested, only the outermost one is recorded.
+    pub fn begin(&mut self) {
+        match &mut self.transaction {
+            Some((_, nesting)) => *nesting += 1,
+            None => self.transaction = Some((WorldEdit::new(), 1)),
+        }
+    }
+
+    /// Ends the current transaction.
+    pub fn commit(&mut self) {
+        match self.transaction.take() {
+            Some((edit, 1)) => self.record(edit),
+            Some((edit, nesting)) => self.transaction = Some((edit, nesting - 1)),
+            None => log::warn!("`EditHistory::commit` called outside of a transaction"),
+        }
+    }
+
+    /// Cancels the whole transaction, reverting its edits in `world`.
+    pub fn abort<A: Accessor, const N: usize>(&mut self, world: &mut VoxelWorld<A, T, N>) {
+        if let Some((edit, _)) = self.transaction.take() {
+            edit.revert(world);
+        }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Reverts the last entry, returning the chunks it changed.
+    pub fn undo<A: Accessor, const N: usize>(
+        &mut self,
+        world: &mut VoxelWorld<A, T, N>,
+    ) -> Option<HashSet<ChunkCoord>> {
+        if self.in_transaction() {
+            log::warn!("cannot undo while a transaction is open");
+            return None;
+        }
+
+        let (edit, size) = self.undo.pop_back()?;
+        self.memory -= size;
+
+        edit.revert(world);
+        let changed = edit.changed_chunks();
+        self.redo.push((edit, size));
+
+        Some(changed)
+    }
+
+    /// Re-applies the last undone entry, returning the chunks it changed.
+    pub fn redo<A: Accessor, const N: usize>(
+        &mut self,
+        world: &mut VoxelWorld<A, T, N>,
+    ) -> Option<HashSet<ChunkCoord>> {
+        if self.in_transaction() {
+            log::warn!("cannot redo while a transaction is open");
+            return None;
+        }
+
+        let (edit, _) = self.redo.pop()?;
+
+        edit.apply(world);
+        let changed = edit.changed_chunks();
+        self.push_undo(edit);
+
+        Some(changed)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Estimated no. of bytes held by undo entries.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.transaction = None;
+        self.memory = 0;
+    }
+
+    fn push_undo(&mut self, edit: WorldEdit<T>) {
+        let size = edit.len() * size_of::<VoxelChange<T>>() + size_of::<WorldEdit<T>>();
+
+        self.memory += size;
+        self.undo.push_back((edit, size));
+
+        while self.undo.len() > self.depth || (self.memory > self.memory_cap && self.undo.len() > 1)
+        {
+            if let Some((_, size)) = self.undo.pop_front() {
+                self.memory -= size;
+            }
+        }
+    }
+}
+
+/// Returns a `System` applying `EditCommand`s to a `VoxelWorld` resource.
+pub fn history_system<A: Accessor, T: Unit, const N: usize>(
+    reader_id: ReaderId<EditCommand>,
+) -> impl Runnable {
+    let mut reader_id = reader_id;
+
+    SystemBuilder::new(format!("EditHistory<{}>System", type_name::<T>()))
+        .read_resource::<EventChannel<EditCommand>>()
+        .write_resource::<EditHistory<T>>()
+        .write_resource::<VoxelWorld<A, T, N>>()
+        .build(move |_, _, (commands, history, world), _| {
+            for command in commands.read(&mut reader_id) {
+                let changed = match command {
+                    EditCommand::Undo => history.undo(&mut **world),
+                    EditCommand::Redo => history.redo(&mut **world),
+                };
+
+                if let Some(changed) = changed {
+                    log::debug!("{:?} changed {} chunks", command, changed.len());
+                }
+            }
+        })
+}
+
+/// Inserts an `EditHistory` and an `EditCommand` channel,
+/// then adds the [`history_system`](history_system).
+pub fn history_routine<A: Accessor, T: Unit, const N: usize>(
+    _: &mut World,
+    r: &mut Resources,
+    b: &mut Builder,
+) {
+    insert_if_none(r, EditHistory::<T>::default());
+
+    if !r.contains::<EventChannel<EditCommand>>() {
+        new_channel::<EditCommand>(r);
+    }
+
+    let id: ReaderId<EditCommand> = subscribe(r);
+    b.add_system(history_system::<A, T, N>(id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        world::edit::{Cuboid, Shape},
+    };
+    use cgmath::Point3;
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    type World = VoxelWorld<Dim, u8, 64>;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_chunk(Point3::new(0, 0, 0), Chunk::default());
+        world.insert_chunk(Point3::new(1, 0, 0), Chunk::default());
+        world
+    }
+
+    fn chunks(world: &World) -> Vec<Option<Chunk<Dim, u8, 64>>> {
+        vec![
+            world.chunk(Point3::new(0, 0, 0)).cloned(),
+            world.chunk(Point3::new(1, 0, 0)).cloned(),
+        ]
+    }
+
+    fn voxel(x: i32) -> impl Shape {
+        Cuboid::new(Point3::new(x, 0, 0), Point3::new(x, 0, 0))
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut world = world();
+        let mut history = EditHistory::default();
+
+        world.set(Point3::new(1, 1, 1), 7);
+        let before = chunks(&world);
+
+        // Straddles both chunks
+        let shape = Cuboid::new(Point3::new(1, 0, 0), Point3::new(5, 1, 1));
+        history.record(world.fill(&shape, 3));
+        let after = chunks(&world);
+
+        let both: HashSet<_> = vec![Point3::new(0, 0, 0), Point3::new(1, 0, 0)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(history.undo(&mut world), Some(both.clone()));
+        assert_eq!(chunks(&world), before);
+        assert!(!history.can_undo());
+
+        assert_eq!(history.redo(&mut world), Some(both));
+        assert_eq!(chunks(&world), after);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn transaction_is_one_step() {
+        let mut world = world();
+        let mut history = EditHistory::default();
+        let before = chunks(&world);
+
+        history.begin();
+        history.record(world.fill(&voxel(0), 1));
+        history.begin();
+        history.record(world.fill(&voxel(5), 2));
+        history.commit();
+        assert!(!history.can_undo());
+        history.commit();
+
+        assert!(history.undo(&mut world).is_some());
+        assert_eq!(chunks(&world), before);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut world = world();
+        let mut history = EditHistory::default();
+
+        history.record(world.fill(&voxel(0), 1));
+        history.undo(&mut world);
+        assert!(history.can_redo());
+
+        history.record(world.fill(&voxel(1), 2));
+        assert!(!history.can_redo());
+        assert_eq!(history.redo(&mut world), None);
+        assert_eq!(world.get(Point3::new(0, 0, 0)), Some(0));
+    }
+
+    #[test]
+    fn caps_evict_oldest() {
+        let mut world = world();
+        let mut history = EditHistory::new(2, usize::MAX);
+
+        for x in 0..3 {
+            history.record(world.fill(&voxel(x), 1));
+        }
+
+        assert!(history.undo(&mut world).is_some());
+        assert!(history.undo(&mut world).is_some());
+        assert_eq!(history.undo(&mut world), None);
+        assert_eq!(world.get(Point3::new(0, 0, 0)), Some(1));
+        assert_eq!(world.get(Point3::new(1, 0, 0)), Some(0));
+
+        // Every single voxel edit has the same estimated size
+        let mut world = self::world();
+        let mut history = EditHistory::default();
+        history.record(world.fill(&voxel(0), 1));
+        let size = history.memory();
+
+        let mut history = EditHistory::new(DEFAULT_HISTORY_DEPTH, 2 * size);
+        for x in 0..3 {
+            history.record(world.fill(&voxel(x), 2));
+        }
+
+        assert_eq!(history.memory(), 2 * size);
+        assert!(history.undo(&mut world).is_some());
+        assert!(history.undo(&mut world).is_some());
+        assert_eq!(history.undo(&mut world), None);
+        assert_eq!(world.get(Point3::new(0, 0, 0)), Some(2));
+    }
+}
@@ -0,0 +1,238 @@
+pub mod block_entity;
+pub mod edit;
+pub mod entity;
+pub mod explosion;
+pub mod generation;
+pub mod history;
+pub mod occlusion;
+pub mod path;
+pub mod region;
+pub mod store;
+
+use crate::{
+    chunk::{Accessor, Chunk, Unit},
+    core::{
+        ecs::{systems::Runnable, *},
+        events::EventChannel,
+    },
+};
+use block_entity::BlockChange;
+use cgmath::Point3;
+use std::{
+    any::type_name,
+    collections::{
+        hash_map::{Iter, IterMut},
+        HashMap, HashSet,
+    },
+};
+
+/// Position of a chunk, in units of chunks.
+pub type ChunkCoord = Point3<i32>;
+
+/// Position of a voxel, in units of voxels.
+pub type VoxelPos = Point3<i32>;
+
+/// Splits a voxel position into the chunk it belongs to
+/// and its local position (YXZ) inside that chunk.
+pub fn split_pos<A: Accessor>(pos: VoxelPos) -> (ChunkCoord, [usize; 3]) {
+    let side = A::SIDE_LEN as i32;
+
+    let coord = Point3::new(
+        pos.x.div_euclid(side),
+        pos.y.div_euclid(side),
+        pos.z.div_euclid(side),
+    );
+
+    let local = [
+        pos.y.rem_euclid(side) as usize,
+        pos.x.rem_euclid(side) as usize,
+        pos.z.rem_euclid(side) as usize,
+    ];
+
+    (coord, local)
+}
+
+/// Inverse of [`split_pos`](split_pos).
+pub fn join_pos<A: Accessor>(coord: ChunkCoord, [y, x, z]: [usize; 3]) -> VoxelPos {
+    let side = A::SIDE_LEN as i32;
+
+    Point3::new(
+        coord.x * side + x as i32,
+        coord.y * side + y as i32,
+        coord.z * side + z as i32,
+    )
+}
+
+/// A world made of chunks addressed by their `ChunkCoord`,
+/// keeping track of which chunks were modified.
+#[derive(Debug, Clone)]
+pub struct VoxelWorld<A: Accessor, T: Unit, const N: usize> {
+    chunks: HashMap<ChunkCoord, Chunk<A, T, N>>,
+    dirty: HashSet<ChunkCoord>,
+    removed: HashSet<ChunkCoord>,
+    changes: Option<Vec<BlockChange<T>>>,
+}
+
+impl<A: Accessor, T: Unit, const N: usize> Default for VoxelWorld<A, T, N> {
+    fn default() -> Self {
+        Self {
+            chunks: HashMap::default(),
+            dirty: HashSet::default(),
+            removed: HashSet::default(),
+            changes: None,
+        }
+    }
+}
+
+impl<A: Accessor, T: Unit, const N: usize> VoxelWorld<A, T, N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn chunk(&self, coord: ChunkCoord) -> Option<&Chunk<A, T, N>> {
+        self.chunks.get(&coord)
+    }
+
+    /// Mutable access to a chunk, marking it as dirty.
+    pub fn chunk_mut(&mut self, coord: ChunkCoord) -> Option<&mut Chunk<A, T, N>> {
+        let chunk = self.chunks.get_mut(&coord)?;
+        self.dirty.insert(coord);
+        Some(chunk)
+    }
+
+    /// Inserts a chunk, returning the one it replaced.
+    pub fn insert_chunk(
+        &mut self,
+        coord: ChunkCoord,
+        chunk: Chunk<A, T, N>,
+    ) -> Option<Chunk<A, T, N>> {
+        self.dirty.insert(coord);
+        self.removed.remove(&coord);
+        self.chunks.insert(coord, chunk)
+    }
+
+    /// Removes a chunk, returning it.
+    ///
+    /// Its block entities stay in the `World`, the caller should despawn them
+    /// with [`BlockEntities::unload_chunk`](block_entity::BlockEntities::unload_chunk).
+    pub fn remove_chunk(&mut self, coord: ChunkCoord) -> Option<Chunk<A, T, N>> {
+        let chunk = self.chunks.remove(&coord)?;
+        self.dirty.remove(&coord);
+        self.removed.insert(coord);
+        Some(chunk)
+    }
+
+    pub fn contains_chunk(&self, coord: ChunkCoord) -> bool {
+        self.chunks.contains_key(&coord)
+    }
+
+    /// Reads a voxel, `None` if its chunk isn't loaded.
+    pub fn get(&self, pos: VoxelPos) -> Option<T> {
+        let (coord, local) = split_pos::<A>(pos);
+        self.chunks.get(&coord).map(|c| c[local])
+    }
+
+    /// Writes a voxel, returning the previous value
+    /// or `None` if its chunk isn't loaded.
+    pub fn set(&mut self, pos: VoxelPos, value: T) -> Option<T> {
+        let (coord, local) = split_pos::<A>(pos);
+        let before = std::mem::replace(&mut self.chunk_mut(coord)?[local], value);
+
+        self.record_change(pos, before, value);
+        Some(before)
+    }
+
+    pub fn iter(&self) -> Iter<'_, ChunkCoord, Chunk<A, T, N>> {
+        self.chunks.iter()
+    }
+
+    /// Iterates over every chunk, without marking them as dirty.
+    pub fn iter_mut(&mut self) -> IterMut<'_, ChunkCoord, Chunk<A, T, N>> {
+        self.chunks.iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn is_dirty(&self, coord: ChunkCoord) -> bool {
+        self.dirty.contains(&coord)
+    }
+
+    /// Marks a chunk as modified.
+    pub fn mark_dirty(&mut self, coord: ChunkCoord) {
+        if self.chunks.contains_key(&coord) {
+            self.dirty.insert(coord);
+        }
+    }
+
+    /// Takes every chunk modified since the last call.
+    /// ## Note
+    /// When the [`chunk_event_system`](chunk_event_system) runs it is the only consumer,
+    /// other systems should read `ChunkEvent`s instead.
+    pub fn take_dirty(&mut self) -> HashSet<ChunkCoord> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Takes every chunk removed since the last call,
+    /// see [`take_dirty`](VoxelWorld::take_dirty).
+    pub fn take_removed(&mut self) -> HashSet<ChunkCoord> {
+        std::mem::take(&mut self.removed)
+    }
+
+    /// Starts recording the voxels changed by [`set`](VoxelWorld::set),
+    /// [`edit`](VoxelWorld::edit) and `WorldEdit::apply`/`revert`.
+    /// Writes through [`chunk_mut`](VoxelWorld::chunk_mut) aren't recorded.
+    pub fn track_changes(&mut self) {
+        self.changes.get_or_insert_with(Vec::new);
+    }
+
+    pub fn is_tracking_changes(&self) -> bool {
+        self.changes.is_some()
+    }
+
+    /// Takes every voxel change recorded since the last call, in order.
+    pub fn take_changes(&mut self) -> Vec<BlockChange<T>> {
+        self.changes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn record_change(&mut self, pos: VoxelPos, before: T, after: T) {
+        if let Some(changes) = &mut self.changes {
+            if before != after {
+                changes.push(BlockChange { pos, before, after });
+            }
+        }
+    }
+}
+
+/// A change in the chunks of a `VoxelWorld`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChunkEvent {
+    /// A chunk was inserted or modified.
+    Changed(ChunkCoord),
+    /// A chunk was removed.
+    Removed(ChunkCoord),
+}
+
+/// Returns a `System` draining the dirty and removed chunks
+/// of the `VoxelWorld` resource into an `EventChannel<ChunkEvent>`,
+/// so that several systems can react to the same changes.
+pub fn chunk_event_system<A: Accessor, T: Unit, const N: usize>() -> impl Runnable {
+    SystemBuilder::new(format!("VoxelWorld<{}>EventSystem", type_name::<T>()))
+        .write_resource::<VoxelWorld<A, T, N>>()
+        .write_resource::<EventChannel<ChunkEvent>>()
+        .build(|_, _, (world, events), _| {
+            let removed = world.take_removed();
+            let dirty = world.take_dirty();
+
+            events.iter_write(removed.into_iter().map(ChunkEvent::Removed));
+            events.iter_write(dirty.into_iter().map(ChunkEvent::Changed));
+        })
+}
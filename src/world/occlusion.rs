@@ -0,0 +1,180 @@
+use super::{entity::ChunkPosition, split_pos, ChunkCoord};
+use crate::{
+    chunk::{mesh::Face, visibility::ChunkConnectivity, Accessor},
+    gfx::{
+        camera::Camera,
+        frustum::{Frustum, Visible},
+    },
+};
+use cgmath::{Point3, SquareMatrix, Vector3, Vector4};
+use legion::{storage::Component, *};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Walks through the chunks reachable from `start` through open space,
+/// returning every chunk that may be visible.
+///
+/// The search never steps back in a direction opposite to one already taken,
+/// and only goes through a chunk if the face it entered from connects to the
+/// face it leaves through. Chunks for which `connectivity` returns `None`,
+/// e.g. unloaded or unstored air chunks, are treated as empty and fully
+/// connected, chunks rejected by `frustum` are skipped.
+pub fn visible_chunks<F>(
+    start: ChunkCoord,
+    max_distance: u32,
+    frustum: Option<&dyn Fn(ChunkCoord) -> bool>,
+    connectivity: F,
+) -> HashSet<ChunkCoord>
+where
+    F: Fn(ChunkCoord) -> Option<ChunkConnectivity>,
+{
+    let mut visible = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    visible.insert(start);
+    queue.push_back((start, None::<Face>, 0u8));
+
+    while let Some((coord, entered, travelled)) = queue.pop_front() {
+        let graph = connectivity(coord).unwrap_or_else(ChunkConnectivity::all);
+
+        for &face in Face::ALL.iter() {
+            if travelled & (1 << face.opposite() as u8) != 0 {
+                continue;
+            }
+
+            if let Some(entered) = entered {
+                if !graph.connects(entered, face) {
+                    continue;
+                }
+            }
+
+            let [dx, dy, dz] = face.normal();
+            let next = coord + Vector3::new(dx, dy, dz);
+
+            let distance =
+                (next.x - start.x).abs() + (next.y - start.y).abs() + (next.z - start.z).abs();
+            if distance as u32 > max_distance || visible.contains(&next) {
+                continue;
+            }
+
+            if let Some(frustum) = frustum {
+                if !frustum(next) {
+                    continue;
+                }
+            }
+
+            visible.insert(next);
+            queue.push_back((next, Some(face.opposite()), travelled | (1 << face as u8)));
+        }
+    }
+
+    visible
+}
+
+/// Approximate world position of a camera, the center of its near plane.
+pub fn camera_position(cam: &Camera) -> Option<Point3<f32>> {
+    let inv = cam.0.invert()?;
+    let p = inv * Vector4::new(0., 0., -1., 1.);
+
+    Some(Point3::new(p.x / p.w, p.y / p.w, p.z / p.w))
+}
+
+/// Occlusion culling system, hides chunk entities that can't be seen
+/// from the camera tagged with `M` through open space.
+///
+/// Should run after the [`culling_system`](crate::gfx::frustum::culling_system),
+/// it only ever turns `Visible` off.
+pub fn occlusion_culling_system<A: Accessor, M: Component>(
+    max_distance: u32,
+) -> impl systems::Runnable {
+    SystemBuilder::new("OcclusionCullingSystem")
+        .with_query(<&Camera>::query().filter(component::<M>()))
+        .with_query(<(&ChunkPosition, &ChunkConnectivity)>::query())
+        .with_query(<(&ChunkPosition, &mut Visible)>::query())
+        .build(move |_, world, _, (cameras, graphs, visibles)| {
+            let cam = match cameras.iter(world).next() {
+                Some(&cam) => cam,
+                None => return,
+            };
+
+            let start = match camera_position(&cam) {
+                Some(p) => split_pos::<A>(p.map(|v| v.floor() as i32)).0,
+                None => return,
+            };
+
+            let frustum = Frustum::from(&cam);
+            let in_frustum =
+                |coord: ChunkCoord| frustum.intersects_aabb(&ChunkPosition(coord).bounds::<A>());
+
+            let graphs: HashMap<ChunkCoord, ChunkConnectivity> = graphs
+                .iter(world)
+                .map(|(pos, &graph)| (**pos, graph))
+                .collect();
+
+            let reached = visible_chunks(start, max_distance, Some(&in_frustum), |coord| {
+                graphs.get(&coord).copied()
+            });
+
+            for (pos, visible) in visibles.iter_mut(world) {
+                if !reached.contains(&**pos) {
+                    **visible = false;
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coord(x: i32, y: i32, z: i32) -> ChunkCoord {
+        Point3::new(x, y, z)
+    }
+
+    #[test]
+    fn camera_in_unloaded_chunk() {
+        let visible = visible_chunks(coord(0, 0, 0), 3, None, |_| None);
+
+        assert!(visible.contains(&coord(0, 0, 0)));
+        assert!(visible.contains(&coord(3, 0, 0)));
+        assert!(visible.contains(&coord(-1, 1, -1)));
+        assert!(!visible.contains(&coord(4, 0, 0)));
+    }
+
+    #[test]
+    fn sparse_world() {
+        // Only a solid chunk is stored, everything around it is air
+        let solid = coord(1, 0, 0);
+        let graph = |c: ChunkCoord| {
+            if c == solid {
+                Some(ChunkConnectivity::none())
+            } else {
+                None
+            }
+        };
+
+        let visible = visible_chunks(coord(0, 0, 0), 4, None, graph);
+
+        assert!(visible.contains(&solid));
+        assert!(visible.contains(&coord(2, 1, 0)));
+        assert!(visible.contains(&coord(-2, 0, 0)));
+        // Right behind the solid chunk
+        assert!(!visible.contains(&coord(2, 0, 0)));
+    }
+
+    #[test]
+    fn enclosed_camera() {
+        // Every stored chunk is solid, the camera's chunk included
+        let visible = visible_chunks(coord(0, 0, 0), 4, None, |_| Some(ChunkConnectivity::none()));
+
+        assert_eq!(visible.len(), 7);
+    }
+
+    #[test]
+    fn frustum_rejects() {
+        let ahead = |c: ChunkCoord| c.x >= 0;
+        let visible = visible_chunks(coord(0, 0, 0), 3, Some(&ahead), |_| None);
+
+        assert!(visible.contains(&coord(2, 0, 0)));
+        assert!(!visible.contains(&coord(-1, 0, 0)));
+    }
+}
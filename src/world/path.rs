@@ -0,0 +1,585 @@
+use super::{VoxelPos, VoxelWorld};
+use crate::{
+    chunk::{Accessor, Unit},
+    core::ecs::{systems::Runnable, *},
+    time::FixedTime,
+};
+use cgmath::{InnerSpace, Point3, Vector3};
+use shrinkwraprs::*;
+use std::{
+    any::type_name,
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+/// Default no. of nodes a single search may expand before giving up.
+pub const DEFAULT_MAX_NODES: usize = 4096;
+
+/// Default no. of nodes expanded per tick by the
+/// [`path_follow_system`](path_follow_system), shared by every agent.
+pub const DEFAULT_FRAME_BUDGET: usize = 512;
+
+/// Size and movement limits of a navigating agent, in voxels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NavAgent {
+    /// Side of the agent's square footprint on the X and Z axes.
+    pub width: u32,
+    pub height: u32,
+    /// Highest step the agent can climb.
+    pub max_jump: u32,
+    /// Highest fall the agent accepts.
+    pub max_drop: u32,
+}
+
+impl Default for NavAgent {
+    fn default() -> Self {
+        Self {
+            width: 1,
+            height: 2,
+            max_jump: 1,
+            max_drop: 3,
+        }
+    }
+}
+
+type SolidFn<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+type CostFn<A, T, const N: usize> =
+    Box<dyn Fn(&VoxelWorld<A, T, N>, VoxelPos) -> Option<f32> + Send + Sync>;
+
+/// A resource describing how agents may walk through a `VoxelWorld`.
+///
+/// Agents stand on solid voxels and only go through non-solid ones,
+/// voxels of unloaded chunks are never entered nor stood on.
+pub struct Navigator<A: Accessor, T: Unit, const N: usize> {
+    solid: SolidFn<T>,
+    cost: Option<CostFn<A, T, N>>,
+    /// No. of nodes a single search may expand before giving up.
+    pub max_nodes: usize,
+    /// No. of nodes expanded per tick by the `path_follow_system`.
+    pub frame_budget: usize,
+}
+
+impl<A: Accessor, T: Unit, const N: usize> Navigator<A, T, N> {
+    pub fn new<F>(solid: F) -> Self
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            solid: Box::new(solid),
+            cost: None,
+            max_nodes: DEFAULT_MAX_NODES,
+            frame_budget: DEFAULT_FRAME_BUDGET,
+        }
+    }
+
+    /// Extra cost of standing at a position, a step costing `1`,
+    /// `None` forbidding it, e.g. to avoid lava.
+    pub fn with_cost<F>(mut self, cost: F) -> Self
+    where
+        F: Fn(&VoxelWorld<A, T, N>, VoxelPos) -> Option<f32> + Send + Sync + 'static,
+    {
+        self.cost = Some(Box::new(cost));
+        self
+    }
+
+    fn passable(&self, world: &VoxelWorld<A, T, N>, pos: VoxelPos) -> bool {
+        world.get(pos).map_or(false, |v| !(self.solid)(&v))
+    }
+
+    /// Whether the footprint of an agent at `pos` is clear
+    /// from `pos.y` up to `pos.y + height`.
+    fn clear(
+        &self,
+        world: &VoxelWorld<A, T, N>,
+        agent: &NavAgent,
+        pos: VoxelPos,
+        height: u32,
+    ) -> bool {
+        footprint(agent, pos)
+            .all(|p| (0..height as i32).all(|y| self.passable(world, p + Vector3::new(0, y, 0))))
+    }
+
+    /// Whether an agent fits at `pos` with solid ground under its feet.
+    pub fn can_stand(&self, world: &VoxelWorld<A, T, N>, agent: &NavAgent, pos: VoxelPos) -> bool {
+        let grounded = footprint(agent, pos).any(|p| {
+            world
+                .get(p - Vector3::unit_y())
+                .map_or(false, |v| (self.solid)(&v))
+        });
+
+        grounded && self.clear(world, agent, pos, agent.height)
+    }
+
+    /// Positions reachable in one step from `pos`, with their cost.
+    pub fn neighbours(
+        &self,
+        world: &VoxelWorld<A, T, N>,
+        agent: &NavAgent,
+        pos: VoxelPos,
+    ) -> Vec<(VoxelPos, f32)> {
+        let mut out = Vec::new();
+
+        for &(dx, dz) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+            for dy in -(agent.max_drop as i32)..=agent.max_jump as i32 {
+                let next = pos + Vector3::new(dx, dy, dz);
+
+                // Headroom to jump up, or room to fall down.
+                let path_clear = if dy > 0 {
+                    self.clear(world, agent, pos, agent.height + dy as u32)
+                } else {
+                    self.clear(world, agent, next, agent.height + (-dy) as u32)
+                };
+
+                if !path_clear || !self.can_stand(world, agent, next) {
+                    continue;
+                }
+
+                let extra = match &self.cost {
+                    Some(cost) => match cost(world, next) {
+                        Some(extra) => extra.max(0.),
+                        None => continue,
+                    },
+                    None => 0.,
+                };
+
+                out.push((next, 1. + dy.abs() as f32 * 0.5 + extra));
+            }
+        }
+
+        out
+    }
+
+    /// Searches a whole path at once, see [`PathSearch`](PathSearch)
+    /// to spread a search across frames.
+    pub fn find_path(
+        &self,
+        world: &VoxelWorld<A, T, N>,
+        agent: NavAgent,
+        start: VoxelPos,
+        goal: VoxelPos,
+    ) -> Option<Vec<VoxelPos>> {
+        let mut search = PathSearch::new(agent, start, goal);
+        search.step(self, world, self.max_nodes);
+
+        match search.status() {
+            PathStatus::Found => Some(search.into_path()),
+            _ => None,
+        }
+    }
+}
+
+/// Voxel columns covered by an agent standing at `pos`.
+fn footprint(agent: &NavAgent, pos: VoxelPos) -> impl Iterator<Item = VoxelPos> {
+    let w = agent.width.max(1) as i32;
+    (0..w * w).map(move |i| pos + Vector3::new(i % w, 0, i / w))
+}
+
+/// Progress of a [`PathSearch`](PathSearch).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathStatus {
+    Searching,
+    Found,
+    NotFound,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Open {
+    estimate: f32,
+    pos: VoxelPos,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Lowest estimate first.
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// An A* search that can be resumed across frames.
+///
+/// Positions are the lowest corner of the agent's footprint
+/// at the height of its feet.
+#[derive(Debug, Clone)]
+pub struct PathSearch {
+    agent: NavAgent,
+    goal: VoxelPos,
+    open: BinaryHeap<Open>,
+    came_from: HashMap<VoxelPos, VoxelPos>,
+    cost: HashMap<VoxelPos, f32>,
+    expanded: usize,
+    status: PathStatus,
+    path: Vec<VoxelPos>,
+}
+
+impl PathSearch {
+    pub fn new(agent: NavAgent, start: VoxelPos, goal: VoxelPos) -> Self {
+        let mut open = BinaryHeap::new();
+        open.push(Open {
+            estimate: heuristic(start, goal),
+            pos: start,
+        });
+
+        let mut cost = HashMap::new();
+        cost.insert(start, 0.);
+
+        Self {
+            agent,
+            goal,
+            open,
+            came_from: HashMap::new(),
+            cost,
+            expanded: 0,
+            status: PathStatus::Searching,
+            path: Vec::new(),
+        }
+    }
+
+    /// Expands at most `budget` nodes, returning how many were expanded.
+    pub fn step<A: Accessor, T: Unit, const N: usize>(
+        &mut self,
+        nav: &Navigator<A, T, N>,
+        world: &VoxelWorld<A, T, N>,
+        budget: usize,
+    ) -> usize {
+        let mut expanded = 0;
+
+        while self.status == PathStatus::Searching && expanded < budget {
+            if self.expanded >= nav.max_nodes {
+                self.status = PathStatus::NotFound;
+                break;
+            }
+
+            let Open { estimate, pos } = match self.open.pop() {
+                Some(open) => open,
+                None => {
+                    self.status = PathStatus::NotFound;
+                    break;
+                }
+            };
+
+            let cost = self.cost[&pos];
+            // Outdated entry of a position reached later by a cheaper way.
+            if estimate > cost + heuristic(pos, self.goal) {
+                continue;
+            }
+
+            expanded += 1;
+            self.expanded += 1;
+
+            if pos == self.goal {
+                self.status = PathStatus::Found;
+                self.path = self.rebuild(pos);
+                break;
+            }
+
+            for (next, step) in nav.neighbours(world, &self.agent, pos) {
+                let next_cost = cost + step;
+
+                if self.cost.get(&next).map_or(true, |&c| next_cost < c) {
+                    self.cost.insert(next, next_cost);
+                    self.came_from.insert(next, pos);
+                    self.open.push(Open {
+                        estimate: next_cost + heuristic(next, self.goal),
+                        pos: next,
+                    });
+                }
+            }
+        }
+
+        expanded
+    }
+
+    fn rebuild(&self, mut pos: VoxelPos) -> Vec<VoxelPos> {
+        let mut path = vec![pos];
+        while let Some(&prev) = self.came_from.get(&pos) {
+            path.push(prev);
+            pos = prev;
+        }
+
+        path.reverse();
+        path
+    }
+
+    pub fn status(&self) -> PathStatus {
+        self.status
+    }
+
+    /// The path from start to goal, once found.
+    pub fn path(&self) -> &[VoxelPos] {
+        &self.path
+    }
+
+    pub fn into_path(self) -> Vec<VoxelPos> {
+        self.path
+    }
+}
+
+/// Horizontal Manhattan distance, never above the real cost
+/// since every step costs at least `1`.
+fn heuristic(a: VoxelPos, b: VoxelPos) -> f32 {
+    ((a.x - b.x).abs() + (a.z - b.z).abs()) as f32
+}
+
+/// Position of the feet of a navigating entity,
+/// at the center of its footprint.
+#[derive(Debug, Clone, Copy, PartialEq, Shrinkwrap)]
+#[shrinkwrap(mutable)]
+pub struct AgentPosition(pub Point3<f32>);
+
+/// A component moving its entity's `AgentPosition` towards a goal.
+#[derive(Debug, Clone)]
+pub struct PathFollower {
+    pub agent: NavAgent,
+    /// Voxels per second.
+    pub speed: f32,
+    goal: Option<VoxelPos>,
+    search: Option<PathSearch>,
+    path: Vec<VoxelPos>,
+    next: usize,
+}
+
+impl PathFollower {
+    pub fn new(agent: NavAgent, speed: f32) -> Self {
+        Self {
+            agent,
+            speed,
+            goal: None,
+            search: None,
+            path: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Searches a path to `goal` from the current position, then follows it.
+    pub fn go_to(&mut self, goal: VoxelPos) {
+        self.stop();
+        self.goal = Some(goal);
+    }
+
+    pub fn stop(&mut self) {
+        self.goal = None;
+        self.search = None;
+        self.path.clear();
+        self.next = 0;
+    }
+
+    pub fn goal(&self) -> Option<VoxelPos> {
+        self.goal
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Waypoints left to reach.
+    pub fn remaining(&self) -> &[VoxelPos] {
+        &self.path[self.next.min(self.path.len())..]
+    }
+
+    fn offset(&self) -> Vector3<f32> {
+        let half = self.agent.width as f32 / 2.;
+        Vector3::new(half, 0., half)
+    }
+}
+
+/// Returns a `System` searching the paths of `PathFollower`s,
+/// within the `Navigator`'s frame budget, and moving them along.
+///
+/// Agents move by one `FixedTime` step per run,
+/// so add it to the fixed schedule, `AppBuilder::fixed`.
+pub fn path_follow_system<A: Accessor, T: Unit, const N: usize>() -> impl Runnable {
+    SystemBuilder::new(format!("PathFollow<{}>System", type_name::<T>()))
+        .read_resource::<Navigator<A, T, N>>()
+        .read_resource::<VoxelWorld<A, T, N>>()
+        .read_resource::<FixedTime>()
+        .with_query(<(&mut PathFollower, &mut AgentPosition)>::query())
+        .build(|_, ecs, (nav, world, fixed), query| {
+            let elapsed = fixed.step_secs();
+            let mut budget = nav.frame_budget;
+
+            for (follower, pos) in query.iter_mut(ecs) {
+                let goal = match follower.goal {
+                    Some(goal) => goal,
+                    None => continue,
+                };
+
+                if follower.path.is_empty() {
+                    if budget == 0 {
+                        continue;
+                    }
+
+                    let agent = follower.agent;
+                    let start = (pos.0 - follower.offset()).map(|v| v.round() as i32);
+                    let search = follower
+                        .search
+                        .get_or_insert_with(|| PathSearch::new(agent, start, goal));
+
+                    budget -= search.step(&**nav, &**world, budget);
+
+                    match search.status() {
+                        PathStatus::Searching => continue,
+                        PathStatus::Found => {
+                            follower.path = follower.search.take().unwrap().into_path();
+                            follower.next = 1;
+                        }
+                        PathStatus::NotFound => {
+                            log::debug!("no path from {:?} to {:?}", start, goal);
+                            follower.stop();
+                            continue;
+                        }
+                    }
+                }
+
+                let mut travel = follower.speed * elapsed;
+                while travel > 0. && follower.next < follower.path.len() {
+                    let target = follower.path[follower.next].map(|v| v as f32) + follower.offset();
+                    let to = target - pos.0;
+                    let distance = to.magnitude();
+
+                    if distance <= travel {
+                        pos.0 = target;
+                        travel -= distance;
+                        follower.next += 1;
+                    } else {
+                        pos.0 += to * (travel / distance);
+                        travel = 0.;
+                    }
+                }
+
+                if follower.next >= follower.path.len() {
+                    follower.stop();
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    type World = VoxelWorld<Dim, u8, 64>;
+
+    /// 12 x 8 x 4 voxels with a solid floor at `y == 0`
+    /// and a wall at `x == 5`, open at `z == 3` if `gap`.
+    fn world(gap: bool) -> World {
+        let mut world = World::new();
+        for x in 0..3 {
+            for y in 0..2 {
+                world.insert_chunk(Point3::new(x, y, 0), Chunk::default());
+            }
+        }
+
+        for x in 0..12 {
+            for z in 0..4 {
+                world.set(Point3::new(x, 0, z), 1);
+            }
+        }
+
+        for y in 1..4 {
+            for z in 0..4 {
+                if !gap || z != 3 {
+                    world.set(Point3::new(5, y, z), 1);
+                }
+            }
+        }
+
+        world
+    }
+
+    fn nav() -> Navigator<Dim, u8, 64> {
+        Navigator::new(|&v| v != 0)
+    }
+
+    #[test]
+    fn found_path() {
+        let mut world = world(true);
+        // A step to climb on the last voxel
+        world.set(Point3::new(11, 1, 0), 1);
+
+        let (start, goal) = (Point3::new(0, 1, 0), Point3::new(11, 2, 0));
+        let path = nav()
+            .find_path(&world, NavAgent::default(), start, goal)
+            .unwrap();
+
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        assert!(path.contains(&Point3::new(5, 1, 3)));
+
+        for step in path.windows(2) {
+            let d = step[1] - step[0];
+            assert_eq!(d.x.abs() + d.z.abs(), 1);
+            assert!(d.y <= 1);
+        }
+    }
+
+    #[test]
+    fn unreachable_goal() {
+        let world = world(false);
+        let mut search = PathSearch::new(
+            NavAgent::default(),
+            Point3::new(0, 1, 0),
+            Point3::new(11, 1, 0),
+        );
+
+        search.step(&nav(), &world, usize::MAX);
+        assert_eq!(search.status(), PathStatus::NotFound);
+        assert!(search.path().is_empty());
+        // Every standable voxel before the wall
+        assert_eq!(search.expanded, 5 * 4);
+    }
+
+    #[test]
+    fn search_budget() {
+        let world = world(true);
+        let (start, goal) = (Point3::new(0, 1, 0), Point3::new(11, 1, 0));
+        let mut search = PathSearch::new(NavAgent::default(), start, goal);
+        let nav = nav();
+
+        // Resumed across frames
+        assert_eq!(search.step(&nav, &world, 1), 1);
+        assert_eq!(search.status(), PathStatus::Searching);
+
+        while search.status() == PathStatus::Searching {
+            assert!(search.step(&nav, &world, 4) <= 4);
+        }
+        assert_eq!(search.status(), PathStatus::Found);
+
+        // Out of nodes
+        let mut nav = nav;
+        nav.max_nodes = 8;
+        assert_eq!(
+            nav.find_path(&world, NavAgent::default(), start, goal),
+            None
+        );
+
+        let mut search = PathSearch::new(NavAgent::default(), start, goal);
+        search.step(&nav, &world, usize::MAX);
+        assert_eq!(search.status(), PathStatus::NotFound);
+        assert_eq!(search.expanded, 8);
+    }
+}
@@ -0,0 +1,746 @@
+//! Region files, storing the chunks of a world on disk.
+//!
+//! A region holds a layer of `REGION_SIDE` x `REGION_SIDE` chunks.
+//! The file is split in 4 KiB sectors, the first one is a table of
+//! chunk locations, the second one their modification timestamps,
+//! the third one a magic number followed by the format version,
+//! and the rest holds the chunks, run-length encoded and followed by
+//! their saved block entities, then compressed.
+//! Files of another version are rejected.
+//!
+//! Regions are never modified in place, every write produces a new file
+//! which then atomically replaces the old one.
+use super::{
+    block_entity::{BlockEntities, BlockEntityHooks},
+    ChunkCoord, VoxelWorld,
+};
+use crate::{
+    chunk::{
+        bytes::{write_varint, ByteReader},
+        Accessor, Chunk, UnitBytes,
+    },
+    core::ecs::World,
+};
+use anyhow::{bail, ensure, Context, Result};
+use cgmath::Point3;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// No. of chunks on the X and Z axes of a region.
+pub const REGION_SIDE: i32 = 32;
+
+/// Version of the region files written.
+pub const REGION_VERSION: u32 = 1;
+
+const REGION_LEN: usize = (REGION_SIDE * REGION_SIDE) as usize;
+const SECTOR_LEN: usize = 4096;
+const HEADER_SECTORS: usize = 3;
+const HEADER_LEN: usize = HEADER_SECTORS * SECTOR_LEN;
+/// Start of the version sector.
+const VERSION_OFFSET: usize = 2 * SECTOR_LEN;
+/// Starts the version sector of every region file.
+const MAGIC: &[u8; 4] = b"VXRG";
+/// Length of a chunk's byte count and compression id.
+const PAYLOAD_HEADER_LEN: usize = 5;
+
+/// Compression of the chunks of a region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compression {
+    None,
+    Zlib,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::Zlib
+    }
+}
+
+impl Compression {
+    fn id(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Zlib => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::None),
+            1 => Some(Self::Zlib),
+            _ => None,
+        }
+    }
+
+    fn compress(self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(bytes),
+            Self::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&bytes)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(bytes.to_vec()),
+            Self::Zlib => {
+                let mut out = Vec::new();
+                ZlibDecoder::new(bytes).read_to_end(&mut out)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// Splits a chunk coordinate into the region it belongs to
+/// and its slot in that region.
+pub fn region_of(coord: ChunkCoord) -> (ChunkCoord, usize) {
+    let region = Point3::new(
+        coord.x.div_euclid(REGION_SIDE),
+        coord.y,
+        coord.z.div_euclid(REGION_SIDE),
+    );
+
+    let slot = coord.x.rem_euclid(REGION_SIDE) + coord.z.rem_euclid(REGION_SIDE) * REGION_SIDE;
+    (region, slot as usize)
+}
+
+/// New payloads of the slots of a region, `None` removing the chunk.
+type SlotChanges = Vec<(usize, Option<Vec<u8>>)>;
+
+/// Sectors used by a chunk, `count` is `0` for absent chunks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Location {
+    sector: u32,
+    count: u8,
+}
+
+impl Location {
+    fn end(&self) -> usize {
+        self.sector as usize + self.count as usize
+    }
+
+    /// The compression id and data of the chunk at this location,
+    /// `None` if the file was cut off before its end.
+    ///
+    /// `from_sector` starts at the chunk's first sector
+    /// and goes up to the end of the file.
+    fn payload<'a>(&self, from_sector: &'a [u8]) -> Option<&'a [u8]> {
+        let sectors = &from_sector[..from_sector.len().min(self.count as usize * SECTOR_LEN)];
+
+        let len = sectors.get(..4)?;
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+
+        sectors.get(4..4usize.checked_add(len)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Header {
+    locations: Vec<Location>,
+    timestamps: Vec<u32>,
+}
+
+impl Header {
+    fn empty() -> Self {
+        Self {
+            locations: vec![Location::default(); REGION_LEN],
+            timestamps: vec![0; REGION_LEN],
+        }
+    }
+
+    /// Reads a header, missing bytes of a truncated file leave
+    /// their chunks absent.
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut header = Self::empty();
+
+        if let Some(v) = bytes.get(VERSION_OFFSET..VERSION_OFFSET + 8) {
+            ensure!(&v[..4] == MAGIC, "not a region file");
+
+            let version = u32::from_le_bytes([v[4], v[5], v[6], v[7]]);
+            ensure!(
+                version == REGION_VERSION,
+                "unsupported region version {}, expected {}",
+                version,
+                REGION_VERSION
+            );
+        }
+
+        let word = |i: usize| {
+            bytes
+                .get(i * 4..i * 4 + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        };
+
+        for slot in 0..REGION_LEN {
+            if let (Some(location), Some(time)) = (word(slot), word(REGION_LEN + slot)) {
+                header.locations[slot] = Location {
+                    sector: location >> 8,
+                    count: location as u8,
+                };
+                header.timestamps[slot] = time;
+            }
+        }
+
+        Ok(header)
+    }
+
+    fn write(&self, out: &mut [u8]) {
+        out[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(MAGIC);
+        out[VERSION_OFFSET + 4..VERSION_OFFSET + 8].copy_from_slice(&REGION_VERSION.to_le_bytes());
+
+        for slot in 0..REGION_LEN {
+            let l = self.locations[slot];
+            let location = (l.sector << 8) | l.count as u32;
+
+            out[slot * 4..slot * 4 + 4].copy_from_slice(&location.to_le_bytes());
+            out[(REGION_LEN + slot) * 4..(REGION_LEN + slot) * 4 + 4]
+                .copy_from_slice(&self.timestamps[slot].to_le_bytes());
+        }
+    }
+}
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or_default()
+}
+
+/// Finds the first run of `count` free sectors, growing `used` if needed.
+fn allocate(used: &mut Vec<bool>, count: usize) -> u32 {
+    let mut start = HEADER_SECTORS;
+
+    while start < used.len() {
+        let end = used.len().min(start + count);
+
+        match used[start..end].iter().rposition(|&u| u) {
+            Some(i) => start += i + 1,
+            None => break,
+        }
+    }
+
+    if used.len() < start + count {
+        used.resize(start + count, false);
+    }
+
+    used[start..start + count]
+        .iter_mut()
+        .for_each(|u| *u = true);
+    start as u32
+}
+
+/// Reads and writes the chunks of a world in a directory of region files.
+#[derive(Debug, Clone)]
+pub struct WorldSave<A: Accessor, T: UnitBytes, const N: usize> {
+    dir: PathBuf,
+    compression: Compression,
+    state: PhantomData<(A, T)>,
+}
+
+impl<A: Accessor, T: UnitBytes, const N: usize> WorldSave<A, T, N> {
+    /// Opens a save directory, creating it if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create save directory {:?}", dir))?;
+
+        Ok(Self {
+            dir,
+            compression: Compression::default(),
+            state: PhantomData::default(),
+        })
+    }
+
+    /// Compression of the chunks written from now on,
+    /// chunks already on disk stay readable.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the file holding a region.
+    pub fn region_path(&self, region: ChunkCoord) -> PathBuf {
+        self.dir
+            .join(format!("r.{}.{}.{}.vxr", region.x, region.y, region.z))
+    }
+
+    fn read_header(&self, file: &mut File) -> Result<Header> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        file.take(HEADER_LEN as u64).read_to_end(&mut bytes)?;
+
+        if bytes.len() < HEADER_LEN {
+            log::warn!("truncated region header, missing chunks are skipped");
+        }
+
+        Header::parse(&bytes)
+    }
+
+    /// Reads a chunk, `None` if it was never saved.
+    ///
+    /// Chunks cut off by a truncated file are treated as never saved.
+    pub fn read_chunk(&self, coord: ChunkCoord) -> Result<Option<Chunk<A, T, N>>> {
+        Ok(self
+            .read_chunk_with_entities(coord)?
+            .map(|(chunk, _)| chunk))
+    }
+
+    /// Reads a chunk and the bytes of its block entities,
+    /// see [`BlockEntities::load_chunk`](BlockEntities::load_chunk).
+    pub fn read_chunk_with_entities(
+        &self,
+        coord: ChunkCoord,
+    ) -> Result<Option<(Chunk<A, T, N>, Vec<u8>)>> {
+        let (region, slot) = region_of(coord);
+
+        let mut file = match File::open(self.region_path(region)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let header = self.read_header(&mut file)?;
+        let location = header.locations[slot];
+        if location.count == 0 {
+            return Ok(None);
+        }
+
+        let mut bytes = Vec::with_capacity(location.count as usize * SECTOR_LEN);
+        file.seek(SeekFrom::Start(location.sector as u64 * SECTOR_LEN as u64))?;
+        file.take(location.count as u64 * SECTOR_LEN as u64)
+            .read_to_end(&mut bytes)?;
+
+        match location.payload(&bytes) {
+            Some(payload) => Self::decode(payload)
+                .map(Some)
+                .with_context(|| format!("corrupted chunk {:?}", coord)),
+            None => {
+                log::warn!("chunk {:?} is truncated, skipped", coord);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Time at which a chunk was last written.
+    pub fn timestamp(&self, coord: ChunkCoord) -> Result<Option<SystemTime>> {
+        let (region, slot) = region_of(coord);
+
+        let mut file = match File::open(self.region_path(region)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let header = self.read_header(&mut file)?;
+        if header.locations[slot].count == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            UNIX_EPOCH + Duration::from_secs(header.timestamps[slot] as u64),
+        ))
+    }
+
+    pub fn write_chunk(&self, coord: ChunkCoord, chunk: &Chunk<A, T, N>) -> Result<()> {
+        self.write_chunks(std::iter::once((coord, chunk)))
+    }
+
+    /// Writes several chunks, rewriting each region they belong to once.
+    ///
+    /// Block entities previously saved with these chunks are dropped,
+    /// see [`write_chunks_with_entities`](WorldSave::write_chunks_with_entities).
+    pub fn write_chunks<'a, I>(&self, chunks: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (ChunkCoord, &'a Chunk<A, T, N>)>,
+        A: 'a,
+    {
+        self.write_chunks_with_entities(
+            chunks
+                .into_iter()
+                .map(|(coord, chunk)| (coord, chunk, Vec::new())),
+        )
+    }
+
+    /// Writes several chunks with the bytes of their block entities,
+    /// see [`BlockEntities::save_chunk`](BlockEntities::save_chunk).
+    pub fn write_chunks_with_entities<'a, I>(&self, chunks: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (ChunkCoord, &'a Chunk<A, T, N>, Vec<u8>)>,
+        A: 'a,
+    {
+        let mut regions: HashMap<ChunkCoord, SlotChanges> = HashMap::new();
+
+        for (coord, chunk, entities) in chunks {
+            let (region, slot) = region_of(coord);
+            let payload = self.encode(chunk, &entities)?;
+
+            regions
+                .entry(region)
+                .or_default()
+                .push((slot, Some(payload)));
+        }
+
+        for (region, changes) in regions {
+            self.rewrite_region(region, changes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes a chunk from its region, freeing its sectors.
+    pub fn remove_chunk(&self, coord: ChunkCoord) -> Result<()> {
+        let (region, slot) = region_of(coord);
+
+        if self.region_path(region).exists() {
+            self.rewrite_region(region, vec![(slot, None)])?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes every chunk of a world.
+    pub fn save_world(&self, world: &VoxelWorld<A, T, N>) -> Result<()> {
+        self.write_chunks(world.iter().map(|(&coord, chunk)| (coord, chunk)))
+    }
+
+    /// Writes every chunk of a world along with its persistent block entities.
+    pub fn save_world_with_entities(
+        &self,
+        world: &VoxelWorld<A, T, N>,
+        ecs: &World,
+        hooks: &BlockEntityHooks<T>,
+        entities: &BlockEntities<A, T>,
+    ) -> Result<()> {
+        self.write_chunks_with_entities(
+            world
+                .iter()
+                .map(|(&coord, chunk)| (coord, chunk, entities.save_chunk(ecs, hooks, coord))),
+        )
+    }
+
+    /// Reads a chunk and respawns its persistent block entities,
+    /// `None` if it was never saved.
+    pub fn load_chunk_with_entities(
+        &self,
+        coord: ChunkCoord,
+        ecs: &mut World,
+        hooks: &BlockEntityHooks<T>,
+        entities: &mut BlockEntities<A, T>,
+    ) -> Result<Option<Chunk<A, T, N>>> {
+        match self.read_chunk_with_entities(coord)? {
+            Some((chunk, bytes)) => {
+                entities
+                    .load_chunk(ecs, hooks, coord, &bytes)
+                    .with_context(|| format!("corrupted block entities in chunk {:?}", coord))?;
+                Ok(Some(chunk))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Compression id and compressed RLE chunk followed by its block entities,
+    /// prefixed by their length.
+    fn encode(&self, chunk: &Chunk<A, T, N>, entities: &[u8]) -> Result<Vec<u8>> {
+        let rle = chunk.encode_rle();
+
+        let mut data = Vec::with_capacity(rle.len() + entities.len() + 4);
+        write_varint(&mut data, rle.len() as u64);
+        data.extend_from_slice(&rle);
+        data.extend_from_slice(entities);
+        let data = self.compression.compress(data)?;
+
+        let mut payload = Vec::with_capacity(PAYLOAD_HEADER_LEN + data.len());
+        payload.extend_from_slice(&(data.len() as u32 + 1).to_le_bytes());
+        payload.push(self.compression.id());
+        payload.extend_from_slice(&data);
+
+        ensure!(
+            payload.len() <= u8::MAX as usize * SECTOR_LEN,
+            "chunk of {} bytes is too large for a region",
+            payload.len()
+        );
+
+        Ok(payload)
+    }
+
+    /// Decodes a payload into a chunk and the bytes of its block entities.
+    fn decode(payload: &[u8]) -> Result<(Chunk<A, T, N>, Vec<u8>)> {
+        let (&id, data) = match payload.split_first() {
+            Some(split) => split,
+            None => bail!("empty chunk payload"),
+        };
+
+        let compression = match Compression::from_id(id) {
+            Some(c) => c,
+            None => bail!("unknown compression {}", id),
+        };
+
+        let data = compression.decompress(data)?;
+        let mut reader = ByteReader::new(&data);
+        let rle_len = reader.varint()? as usize;
+        let chunk = Chunk::decode_rle(reader.take(rle_len)?)?;
+
+        Ok((chunk, reader.rest().to_vec()))
+    }
+
+    /// Writes a new version of a region next to the old one, then replaces it.
+    ///
+    /// Sectors of the replaced and removed chunks are reused,
+    /// and trailing free sectors are dropped.
+    fn rewrite_region(&self, region: ChunkCoord, changes: SlotChanges) -> Result<()> {
+        let path = self.region_path(region);
+
+        let mut bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut header =
+            Header::parse(&bytes).with_context(|| format!("failed to read region {:?}", path))?;
+
+        let file_sectors = (bytes.len() + SECTOR_LEN - 1) / SECTOR_LEN;
+        let mut used = vec![false; file_sectors.max(HEADER_SECTORS)];
+        used[..HEADER_SECTORS].iter_mut().for_each(|u| *u = true);
+
+        for (slot, location) in header.locations.iter_mut().enumerate() {
+            if location.count == 0 {
+                continue;
+            }
+
+            // Same check as `read_chunk`, chunks cut off by the end of the file are dropped
+            let start = location.sector as usize;
+            let complete = bytes
+                .get(start * SECTOR_LEN..)
+                .and_then(|b| location.payload(b))
+                .is_some();
+
+            if used.len() < location.end() {
+                used.resize(location.end(), false);
+            }
+
+            let valid = start >= HEADER_SECTORS
+                && complete
+                && !used[start..location.end()].iter().any(|&u| u);
+
+            if valid {
+                used[location.sector as usize..location.end()]
+                    .iter_mut()
+                    .for_each(|u| *u = true);
+            } else {
+                log::warn!("dropped invalid chunk location in {:?}", path);
+                *location = Location::default();
+                header.timestamps[slot] = 0;
+            }
+        }
+
+        for &(slot, _) in changes.iter() {
+            let old = std::mem::take(&mut header.locations[slot]);
+            used[old.sector as usize..old.end()]
+                .iter_mut()
+                .for_each(|u| *u = false);
+            header.timestamps[slot] = 0;
+        }
+
+        let time = now();
+        let mut writes: Vec<(usize, usize, Vec<u8>)> = Vec::new();
+
+        for (slot, payload) in changes {
+            // The same chunk may be written twice in a batch.
+            let old = std::mem::take(&mut header.locations[slot]);
+            used[old.sector as usize..old.end()]
+                .iter_mut()
+                .for_each(|u| *u = false);
+            writes.retain(|&(s, ..)| s != slot);
+
+            if let Some(payload) = payload {
+                let count = (payload.len() + SECTOR_LEN - 1) / SECTOR_LEN;
+                let sector = allocate(&mut used, count);
+
+                header.locations[slot] = Location {
+                    sector,
+                    count: count as u8,
+                };
+                header.timestamps[slot] = time;
+                writes.push((slot, sector as usize, payload));
+            }
+        }
+
+        let end = used.iter().rposition(|&u| u).map_or(0, |i| i + 1);
+        bytes.resize(end.max(HEADER_SECTORS) * SECTOR_LEN, 0);
+        header.write(&mut bytes[..HEADER_LEN]);
+
+        for (_, sector, payload) in writes {
+            let start = sector * SECTOR_LEN;
+            let padded = (payload.len() + SECTOR_LEN - 1) / SECTOR_LEN * SECTOR_LEN;
+
+            bytes[start..start + payload.len()].copy_from_slice(&payload);
+            bytes[start + payload.len()..start + padded]
+                .iter_mut()
+                .for_each(|b| *b = 0);
+        }
+
+        let temp = path.with_extension("vxr.tmp");
+        {
+            let mut file = File::create(&temp)?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+        }
+
+        fs::rename(&temp, &path).with_context(|| format!("failed to replace region {:?}", path))?;
+
+        // Persists the rename itself, not supported everywhere.
+        if let Ok(dir) = File::open(&self.dir) {
+            dir.sync_all().ok();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shrinkwraprs::*;
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    type Save = WorldSave<Dim, u16, 64>;
+
+    /// A save in a temporary directory, deleted once dropped.
+    #[derive(Shrinkwrap)]
+    struct TempSave(Save);
+
+    impl Drop for TempSave {
+        fn drop(&mut self) {
+            fs::remove_dir_all(self.0.dir()).ok();
+        }
+    }
+
+    fn save(name: &str) -> TempSave {
+        let dir = std::env::temp_dir().join(format!("region-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+
+        TempSave(Save::open(dir).unwrap().with_compression(Compression::None))
+    }
+
+    fn chunk(seed: u16) -> Chunk<Dim, u16, 64> {
+        let mut chunk = Chunk::default();
+        for i in 0..64 {
+            chunk[[i / 16, i / 4 % 4, i % 4]] = seed + i as u16;
+        }
+
+        chunk
+    }
+
+    fn coord(x: i32) -> ChunkCoord {
+        Point3::new(x, 0, 0)
+    }
+
+    #[test]
+    fn round_trip() {
+        let save = save("round-trip");
+        save.write_chunks(vec![(coord(0), &chunk(1)), (coord(-1), &chunk(2))])
+            .unwrap();
+
+        assert_eq!(save.read_chunk(coord(0)).unwrap(), Some(chunk(1)));
+        assert_eq!(save.read_chunk(coord(-1)).unwrap(), Some(chunk(2)));
+        assert_eq!(save.read_chunk(coord(1)).unwrap(), None);
+
+        save.remove_chunk(coord(0)).unwrap();
+        assert_eq!(save.read_chunk(coord(0)).unwrap(), None);
+        assert_eq!(save.read_chunk(coord(-1)).unwrap(), Some(chunk(2)));
+    }
+
+    #[test]
+    fn truncated_chunk_is_absent() {
+        let save = save("truncated");
+        save.write_chunk(coord(0), &chunk(1)).unwrap();
+        save.write_chunk(coord(1), &chunk(2)).unwrap();
+
+        // Cuts the last chunk in the middle of its payload
+        let path = save.region_path(coord(0));
+        let bytes = fs::read(&path).unwrap();
+        let last = Header::parse(&bytes).unwrap().locations[1];
+        fs::write(&path, &bytes[..last.sector as usize * SECTOR_LEN + 20]).unwrap();
+
+        assert_eq!(save.read_chunk(coord(0)).unwrap(), Some(chunk(1)));
+        assert_eq!(save.read_chunk(coord(1)).unwrap(), None);
+
+        // Rewriting the region drops the cut off chunk instead of padding it
+        save.write_chunk(coord(2), &chunk(3)).unwrap();
+        assert_eq!(save.read_chunk(coord(0)).unwrap(), Some(chunk(1)));
+        assert_eq!(save.read_chunk(coord(1)).unwrap(), None);
+        assert_eq!(save.read_chunk(coord(2)).unwrap(), Some(chunk(3)));
+    }
+
+    #[test]
+    fn truncated_header() {
+        let save = save("truncated-header");
+        save.write_chunk(coord(0), &chunk(1)).unwrap();
+
+        let path = save.region_path(coord(0));
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..100]).unwrap();
+
+        assert_eq!(save.read_chunk(coord(0)).unwrap(), None);
+        save.write_chunk(coord(1), &chunk(2)).unwrap();
+        assert_eq!(save.read_chunk(coord(1)).unwrap(), Some(chunk(2)));
+    }
+
+    #[test]
+    fn block_entities_round_trip() {
+        let save = save("entities");
+        save.write_chunks_with_entities(vec![(coord(0), &chunk(1), vec![1, 2, 3])])
+            .unwrap();
+
+        assert_eq!(
+            save.read_chunk_with_entities(coord(0)).unwrap(),
+            Some((chunk(1), vec![1, 2, 3]))
+        );
+
+        save.write_chunk(coord(0), &chunk(2)).unwrap();
+        assert_eq!(
+            save.read_chunk_with_entities(coord(0)).unwrap(),
+            Some((chunk(2), Vec::new()))
+        );
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let save = save("newer");
+        save.write_chunk(coord(0), &chunk(1)).unwrap();
+
+        let path = save.region_path(coord(0));
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[VERSION_OFFSET + 4..VERSION_OFFSET + 8]
+            .copy_from_slice(&(REGION_VERSION + 1).to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+
+        assert!(save.read_chunk(coord(0)).is_err());
+        assert!(save.write_chunk(coord(1), &chunk(2)).is_err());
+
+        bytes[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(b"NOPE");
+        fs::write(&path, &bytes).unwrap();
+        assert!(save.read_chunk(coord(0)).is_err());
+    }
+}
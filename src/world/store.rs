@@ -0,0 +1,238 @@
+use super::{ChunkCoord, ChunkEvent, VoxelWorld};
+use crate::{
+    chunk::{Accessor, Chunk, Unit},
+    core::{
+        ecs::{systems::Runnable, *},
+        events::{EventChannel, ReaderId},
+    },
+};
+use evmap::{ReadHandle, ReadHandleFactory, WriteHandle};
+use std::{any::type_name, sync::Mutex};
+
+/// A concurrent copy of a `VoxelWorld`'s chunks.
+///
+/// Readers see the chunks as they were at the last
+/// [`publish`](ChunkStore::publish) without ever blocking the writer,
+/// so meshing/rendering threads can run while the simulation edits the world.
+pub struct ChunkStore<A: Accessor, T: Unit, const N: usize> {
+    // Only needed to make the store `Sync`, the write handle
+    // is always accessed through `&mut self`.
+    write: Mutex<WriteHandle<ChunkCoord, Chunk<A, T, N>>>,
+    factory: ReadHandleFactory<ChunkCoord, Chunk<A, T, N>>,
+}
+
+impl<A: Accessor, T: Unit, const N: usize> Default for ChunkStore<A, T, N> {
+    fn default() -> Self {
+        let (read, write) = evmap::new();
+
+        Self {
+            write: Mutex::new(write),
+            factory: read.factory(),
+        }
+    }
+}
+
+impl<A: Accessor, T: Unit, const N: usize> ChunkStore<A, T, N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn write(&mut self) -> &mut WriteHandle<ChunkCoord, Chunk<A, T, N>> {
+        self.write
+            .get_mut()
+            .expect("`ChunkStore` write handle was poisoned")
+    }
+
+    /// Stages a chunk, visible to readers after the next publish.
+    pub fn insert(&mut self, coord: ChunkCoord, chunk: Chunk<A, T, N>) {
+        self.write().update(coord, chunk);
+    }
+
+    /// Stages the removal of a chunk, effective after the next publish.
+    pub fn remove(&mut self, coord: ChunkCoord) {
+        self.write().empty(coord);
+    }
+
+    /// Stages the chunks changed or removed by `events`,
+    /// as written by the [`chunk_event_system`](super::chunk_event_system)
+    /// which is the only consumer of the world's dirty chunks.
+    /// Returns the no. of staged changes.
+    pub fn stage_events<'a, I>(&mut self, world: &VoxelWorld<A, T, N>, events: I) -> usize
+    where
+        I: IntoIterator<Item = &'a ChunkEvent>,
+    {
+        let write = self.write();
+        let mut staged = 0;
+
+        for event in events {
+            match *event {
+                ChunkEvent::Changed(coord) => match world.chunk(coord) {
+                    Some(&chunk) => {
+                        write.update(coord, chunk);
+                    }
+                    None => continue,
+                },
+
+                ChunkEvent::Removed(coord) => {
+                    write.empty(coord);
+                }
+            }
+
+            staged += 1;
+        }
+
+        staged
+    }
+
+    /// Exposes every staged change to readers at once.
+    pub fn publish(&mut self) {
+        self.write().refresh();
+    }
+
+    /// A handle that can be sent to other threads for reading.
+    pub fn reader(&self) -> ChunkReader<A, T, N> {
+        ChunkReader {
+            factory: self.factory.clone(),
+        }
+    }
+}
+
+/// A `Send + Sync` entry point for reading a `ChunkStore`,
+/// each thread should create its own [`view`](ChunkReader::view).
+#[derive(Clone)]
+pub struct ChunkReader<A: Accessor, T: Unit, const N: usize> {
+    factory: ReadHandleFactory<ChunkCoord, Chunk<A, T, N>>,
+}
+
+impl<A: Accessor, T: Unit, const N: usize> ChunkReader<A, T, N> {
+    pub fn view(&self) -> ChunkView<A, T, N> {
+        ChunkView {
+            read: self.factory.handle(),
+        }
+    }
+}
+
+/// A thread-local view of the last published chunks.
+#[derive(Clone)]
+pub struct ChunkView<A: Accessor, T: Unit, const N: usize> {
+    read: ReadHandle<ChunkCoord, Chunk<A, T, N>>,
+}
+
+impl<A: Accessor, T: Unit, const N: usize> ChunkView<A, T, N> {
+    /// Copies a chunk out of the store.
+    pub fn get(&self, coord: ChunkCoord) -> Option<Chunk<A, T, N>> {
+        self.read.get_one(&coord).map(|c| *c)
+    }
+
+    /// Borrows a chunk without copying it, readers holding
+    /// the chunk delay the writer's next publish.
+    pub fn with<R, F: FnOnce(&Chunk<A, T, N>) -> R>(&self, coord: ChunkCoord, f: F) -> Option<R> {
+        self.read.get_one(&coord).map(|c| f(&*c))
+    }
+
+    pub fn contains(&self, coord: ChunkCoord) -> bool {
+        self.read.contains_key(&coord)
+    }
+
+    /// Coordinates of every published chunk.
+    pub fn coords(&self) -> Vec<ChunkCoord> {
+        self.read.map_into(|&coord, _| coord)
+    }
+
+    pub fn len(&self) -> usize {
+        self.read.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read.is_empty()
+    }
+}
+
+/// Returns a `System` staging the `ChunkEvent`s of the `VoxelWorld`
+/// resource in the `ChunkStore` resource, then publishing them.
+/// Should run once per frame, after the [`chunk_event_system`](super::chunk_event_system).
+pub fn publish_system<A: Accessor, T: Unit, const N: usize>(
+    reader_id: ReaderId<ChunkEvent>,
+) -> impl Runnable {
+    let mut reader_id = reader_id;
+
+    SystemBuilder::new(format!("ChunkStore<{}>PublishSystem", type_name::<T>()))
+        .read_resource::<VoxelWorld<A, T, N>>()
+        .read_resource::<EventChannel<ChunkEvent>>()
+        .write_resource::<ChunkStore<A, T, N>>()
+        .build(move |_, _, (world, events, store), _| {
+            if store.stage_events(world, events.read(&mut reader_id)) > 0 {
+                store.publish();
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::events::{new_channel, subscribe},
+        world::chunk_event_system,
+    };
+    use cgmath::Point3;
+
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct Dim;
+
+    impl Accessor for Dim {
+        const SIDE_LEN: usize = 4;
+    }
+
+    type Voxels = VoxelWorld<Dim, u8, 64>;
+
+    #[test]
+    fn published_by_system() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        resources.insert(Voxels::new());
+        resources.insert(ChunkStore::<Dim, u8, 64>::new());
+        new_channel::<ChunkEvent>(&mut resources);
+
+        let id = subscribe::<ChunkEvent>(&mut resources);
+        let mut schedule = Schedule::builder()
+            .add_system(chunk_event_system::<Dim, u8, 64>())
+            .add_system(publish_system::<Dim, u8, 64>(id))
+            .build();
+
+        let view = {
+            let store = resources.get::<ChunkStore<Dim, u8, 64>>().unwrap();
+            store.reader().view()
+        };
+        let voxel =
+            |view: &ChunkView<Dim, u8, 64>| view.with(Point3::new(1, 0, 0), |c| c[[0, 0, 0]]);
+
+        let mut chunk = Chunk::default();
+        chunk[[0, 0, 0]] = 3;
+        resources
+            .get_mut::<Voxels>()
+            .unwrap()
+            .insert_chunk(Point3::new(1, 0, 0), chunk);
+
+        assert!(view.is_empty());
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(voxel(&view), Some(3));
+
+        // Edits are only seen once published
+        resources
+            .get_mut::<Voxels>()
+            .unwrap()
+            .set(Point3::new(4, 0, 0), 5);
+        assert_eq!(voxel(&view), Some(3));
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(voxel(&view), Some(5));
+
+        resources
+            .get_mut::<Voxels>()
+            .unwrap()
+            .remove_chunk(Point3::new(1, 0, 0));
+        assert_eq!(voxel(&view), Some(5));
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(voxel(&view), None);
+        assert!(view.is_empty());
+    }
+}
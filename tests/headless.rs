@@ -0,0 +1,122 @@
+//! Runs a headless `App` frame by frame, as a dedicated server would.
+use voxl::{
+    app::{App, AppBuilder, ResumeApp},
+    core::ecs::{systems::Runnable, *},
+    stage::Stage,
+    state::{State, StateStack},
+    time::{FixedTime, Interpolation},
+};
+
+struct Menu;
+impl State for Menu {}
+
+struct Playing;
+impl State for Playing {}
+
+#[derive(Debug, Default)]
+struct Counters {
+    started: u32,
+    frames: u32,
+    ticks: u32,
+    menu: u32,
+    entered: u32,
+    playing: u32,
+    stopped: u32,
+}
+
+fn count(name: &'static str, f: fn(&mut Counters)) -> impl Runnable {
+    SystemBuilder::new(name)
+        .write_resource::<Counters>()
+        .build(move |_, _, counters, _| f(&mut **counters))
+}
+
+/// Leaves the menu on its second frame.
+fn menu_system() -> impl Runnable {
+    SystemBuilder::new("MenuSystem")
+        .write_resource::<Counters>()
+        .write_resource::<StateStack>()
+        .build(|_, _, (counters, states), _| {
+            counters.menu += 1;
+            if counters.menu == 2 {
+                states.switch::<Playing>();
+            }
+        })
+}
+
+fn builder() -> AppBuilder {
+    let mut builder = App::headless()
+        .num_threads(2)
+        .tick_rate(20)
+        .routine_fn_in(Stage::Startup, |_, r, b| {
+            r.insert(Counters::default());
+            b.add_system(count("Startup", |c| c.started += 1));
+        })
+        .routine_fn_in(Stage::Shutdown, |_, _, b| {
+            b.add_system(count("Shutdown", |c| c.stopped += 1));
+        })
+        .routine_fn(|_, _, b| {
+            b.add_system(count("Frames", |c| c.frames += 1));
+        })
+        .state::<Menu, _>(|_, _, s| {
+            s.update.add_system(menu_system());
+        })
+        .state::<Playing, _>(|_, _, s| {
+            s.enter
+                .add_system(count("EnterPlaying", |c| c.entered += 1));
+            s.update.add_system(count("Playing", |c| c.playing += 1));
+        })
+        .initial_state::<Menu>();
+
+    builder.fixed.add_system(count("Ticks", |c| c.ticks += 1));
+    builder
+}
+
+#[test]
+fn tick() {
+    let mut app = builder().build();
+
+    for _ in 0..3 {
+        app.tick();
+    }
+
+    let r = app.resources();
+    let counters = r.get::<Counters>().unwrap();
+    assert_eq!(counters.started, 1);
+    assert_eq!((counters.frames, counters.ticks), (3, 3));
+    assert_eq!(r.get::<FixedTime>().unwrap().tick(), 3);
+    assert_eq!(**r.get::<Interpolation>().unwrap(), 0.);
+
+    // Switched at the end of the second frame
+    assert!(r.get::<StateStack>().unwrap().is::<Playing>());
+    assert_eq!(
+        (counters.menu, counters.entered, counters.playing),
+        (2, 1, 1)
+    );
+    assert_eq!(counters.stopped, 0);
+}
+
+#[test]
+fn run_for() {
+    let mut app = builder()
+        .routine_fn(|_, _, b| {
+            b.add_system(
+                SystemBuilder::new("EndSystem")
+                    .read_resource::<Counters>()
+                    .write_resource::<ResumeApp>()
+                    .build(|_, _, (counters, resume), _| {
+                        if counters.frames == 5 {
+                            resume.end();
+                        }
+                    }),
+            );
+        })
+        .build();
+
+    app.run_for(100);
+    app.shutdown();
+    app.shutdown();
+
+    let counters = app.resources().get::<Counters>().unwrap();
+    assert_eq!((counters.frames, counters.ticks), (5, 5));
+    assert_eq!((counters.started, counters.stopped), (1, 1));
+}